@@ -1,16 +1,20 @@
 #!/bin/bash
 
 SCRIPT_DIR=$(dirname "$(readlink -f "$0")")
+# Local dependencies (`./`) are relative to the kraglefiles
+cd "$SCRIPT_DIR" || exit 1
 
 # pip install pre-commit
 # pre-commit autoupdate --repo https://github.com/pre-commit/pre-commit-hooks
 
-LIST=$(find "$SCRIPT_DIR" -type f \( -name "*.json" -o -name "*.yaml" -o -name "*.yml" \) -exec echo "{}" \;)
+STATUS=0
+LIST=$(find "$SCRIPT_DIR" -type f \( -name "*.json" -o -name "*.yaml" -o -name "*.yml" -o -name "*.toml" \) ! -name "manifest.yaml")
 for FILE in $LIST; do
+    cargo run -q -r -- lint "$FILE" || STATUS=1
+
     DIR_TMP=$(mktemp -d)
 
-    cargo run -r -- import "" "$DIR_TMP"
-$FILE
+    cargo run -q -r -- import "$FILE" "$DIR_TMP" || STATUS=1
     # if [ -f "$DIR_TMP/.pre-commit-config.yaml" ]; then
     #     cd $DIR_TMP
     #     git init
@@ -22,7 +26,8 @@ $FILE
     #     cd -
     # fi
 
-    cargo run -r -- validate "$FILE" "$DIR_TMP"
+    cargo run -q -r -- validate "$FILE" "$DIR_TMP" || STATUS=1
 
     rm -rf "$DIR_TMP"
 done
+exit $STATUS
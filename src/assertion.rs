@@ -0,0 +1,257 @@
+use std::fmt;
+
+use regex::RegexBuilder;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// How strictly a file node is compared against the file on disk.
+///
+/// Each level includes the checks of the previous ones.
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    /// The file must exist
+    Exists,
+    /// The file size must match `original_size`
+    Size,
+    /// The file md5 must match `original_md5`
+    #[default]
+    Hash,
+    /// The file must be byte-for-byte equal to the embedded `content`
+    Content,
+}
+
+/// Constraint on the number of lines of a file
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+pub struct LineCount {
+    pub min: Option<usize>,
+    pub max: Option<usize>,
+}
+
+/// Assertion evaluated against the content of a file on disk
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(try_from = "AssertionEntry", into = "AssertionEntry")]
+pub enum Assertion {
+    /// The file must contain the given text
+    Contains(String),
+    /// The file must match the given regex (multi-line mode, `^` and `$` match at line boundaries)
+    Matches(String),
+    /// The file must not contain the given text
+    NotContains(String),
+    /// The file must not be larger than the given number of bytes
+    MaxSize(u64),
+    /// The number of lines of the file must be within bounds
+    LineCount(LineCount),
+}
+
+/// Serialized form of an assertion: a map with a single key (e.g. `contains: foo`)
+#[derive(Serialize, Deserialize, JsonSchema, Default)]
+#[serde(deny_unknown_fields)]
+#[schemars(extend("minProperties" = 1, "maxProperties" = 1))]
+struct AssertionEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    contains: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matches: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    not_contains: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    line_count: Option<LineCount>,
+}
+
+impl TryFrom<AssertionEntry> for Assertion {
+    type Error = String;
+
+    fn try_from(entry: AssertionEntry) -> Result<Self, Self::Error> {
+        let mut assertions = Vec::new();
+        assertions.extend(entry.contains.map(Assertion::Contains));
+        assertions.extend(entry.matches.map(Assertion::Matches));
+        assertions.extend(entry.not_contains.map(Assertion::NotContains));
+        assertions.extend(entry.max_size.map(Assertion::MaxSize));
+        assertions.extend(entry.line_count.map(Assertion::LineCount));
+
+        match assertions.len() {
+            1 => Ok(assertions.remove(0)),
+            _ => Err("an assertion must have exactly one key".to_string()),
+        }
+    }
+}
+
+impl From<Assertion> for AssertionEntry {
+    fn from(assertion: Assertion) -> Self {
+        let mut entry = AssertionEntry::default();
+        match assertion {
+            Assertion::Contains(needle) => entry.contains = Some(needle),
+            Assertion::Matches(pattern) => entry.matches = Some(pattern),
+            Assertion::NotContains(needle) => entry.not_contains = Some(needle),
+            Assertion::MaxSize(max_size) => entry.max_size = Some(max_size),
+            Assertion::LineCount(line_count) => entry.line_count = Some(line_count),
+        }
+        entry
+    }
+}
+
+impl Assertion {
+    /// Evaluates the assertion against the file content, returns the failure reason
+    pub fn check(&self, data: &[u8]) -> Result<(), String> {
+        let text = String::from_utf8_lossy(data);
+        match self {
+            Assertion::Contains(needle) => {
+                if text.contains(needle.as_str()) {
+                    Ok(())
+                } else {
+                    Err(format!("{:?} not found", needle))
+                }
+            }
+            Assertion::Matches(pattern) => {
+                let regex = RegexBuilder::new(pattern)
+                    .multi_line(true)
+                    .build()
+                    .map_err(|e| format!("invalid regex: {}", e))?;
+                if regex.is_match(&text) {
+                    Ok(())
+                } else {
+                    Err("no match found".to_string())
+                }
+            }
+            Assertion::NotContains(needle) => match text.find(needle.as_str()) {
+                Some(offset) => Err(format!(
+                    "{:?} found at line {}",
+                    needle,
+                    text[..offset].matches('\n').count() + 1
+                )),
+                None => Ok(()),
+            },
+            Assertion::MaxSize(max_size) => {
+                let size = data.len() as u64;
+                if size <= *max_size {
+                    Ok(())
+                } else {
+                    Err(format!("size is {} bytes", size))
+                }
+            }
+            Assertion::LineCount(LineCount { min, max }) => {
+                let count = text.lines().count();
+                if min.is_some_and(|min| count < min) || max.is_some_and(|max| count > max) {
+                    Err(format!("file has {} lines", count))
+                } else {
+                    Ok(())
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Contains(needle) => write!(f, "contains {:?}", needle),
+            Assertion::Matches(pattern) => write!(f, "matches /{}/", pattern),
+            Assertion::NotContains(needle) => write!(f, "not_contains {:?}", needle),
+            Assertion::MaxSize(max_size) => write!(f, "max_size {}", max_size),
+            Assertion::LineCount(LineCount { min, max }) => {
+                write!(f, "line_count")?;
+                if let Some(min) = min {
+                    write!(f, " min {}", min)?;
+                }
+                if let Some(max) = max {
+                    write!(f, " max {}", max)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CARGO_TOML: &[u8] = b"[package]\nname = \"kragle\"\nedition = \"2024\"\n";
+
+    #[test]
+    fn test_contains() {
+        assert!(
+            Assertion::Contains("edition = \"2024\"".to_string())
+                .check(CARGO_TOML)
+                .is_ok()
+        );
+        assert!(
+            Assertion::Contains("edition = \"2021\"".to_string())
+                .check(CARGO_TOML)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_not_contains() {
+        assert!(
+            Assertion::NotContains("TODO".to_string())
+                .check(CARGO_TOML)
+                .is_ok()
+        );
+        assert_eq!(
+            Assertion::NotContains("edition".to_string()).check(CARGO_TOML),
+            Err("\"edition\" found at line 3".to_string())
+        );
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(
+            Assertion::Matches("^name = \".+\"$".to_string())
+                .check(CARGO_TOML)
+                .is_ok()
+        );
+        assert!(
+            Assertion::Matches("^version = ".to_string())
+                .check(CARGO_TOML)
+                .is_err()
+        );
+        assert!(
+            Assertion::Matches("(".to_string())
+                .check(CARGO_TOML)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_max_size_and_line_count() {
+        assert!(Assertion::MaxSize(1024).check(CARGO_TOML).is_ok());
+        assert!(Assertion::MaxSize(8).check(CARGO_TOML).is_err());
+
+        let line_count = |min, max| Assertion::LineCount(LineCount { min, max });
+        assert!(line_count(Some(1), Some(3)).check(CARGO_TOML).is_ok());
+        assert!(line_count(Some(4), None).check(CARGO_TOML).is_err());
+        assert!(line_count(None, Some(2)).check(CARGO_TOML).is_err());
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let assertions: Vec<Assertion> =
+            serde_yml::from_str("- contains: foo\n- max_size: 10\n- line_count: {min: 1}\n")
+                .unwrap();
+        assert_eq!(
+            assertions,
+            vec![
+                Assertion::Contains("foo".to_string()),
+                Assertion::MaxSize(10),
+                Assertion::LineCount(LineCount {
+                    min: Some(1),
+                    max: None
+                }),
+            ]
+        );
+
+        let serialized = serde_yml::to_string(&assertions).unwrap();
+        assert_eq!(
+            serde_yml::from_str::<Vec<Assertion>>(&serialized).unwrap(),
+            assertions
+        );
+        assert!(serde_yml::from_str::<Vec<Assertion>>("- {contains: a, max_size: 1}\n").is_err());
+    }
+}
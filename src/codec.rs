@@ -0,0 +1,431 @@
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::RangeInclusive;
+
+use base64::Engine;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use xz2::read::{XzDecoder, XzEncoder};
+
+/// Compression applied to the content of a file
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    /// Stored as is
+    #[default]
+    None,
+    Xz,
+    Zstd,
+    Gzip,
+    Brotli,
+}
+
+impl fmt::Display for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Codec::None => "none",
+            Codec::Xz => "xz",
+            Codec::Zstd => "zstd",
+            Codec::Gzip => "gzip",
+            Codec::Brotli => "brotli",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Codec {
+    /// Levels accepted by the codec
+    pub fn levels(self) -> RangeInclusive<u32> {
+        match self {
+            Codec::None => 0..=0,
+            Codec::Xz | Codec::Gzip => 0..=9,
+            Codec::Zstd => 1..=22,
+            Codec::Brotli => 0..=11,
+        }
+    }
+
+    /// Level used when none is given
+    pub fn default_level(self) -> u32 {
+        match self {
+            Codec::None => 0,
+            Codec::Xz | Codec::Gzip => 6,
+            Codec::Zstd => 3,
+            Codec::Brotli => 9,
+        }
+    }
+
+    pub fn compress(self, data: &[u8], level: u32) -> io::Result<Vec<u8>> {
+        let mut compressed = Vec::new();
+        match self {
+            Codec::None => compressed.extend_from_slice(data),
+            Codec::Xz => {
+                XzEncoder::new(data, level).read_to_end(&mut compressed)?;
+            }
+            Codec::Zstd => compressed = zstd::encode_all(data, level as i32)?,
+            Codec::Gzip => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(&mut compressed, flate2::Compression::new(level));
+                encoder.write_all(data)?;
+                encoder.finish()?;
+            }
+            Codec::Brotli => {
+                brotli::CompressorReader::new(data, 4096, level, 22)
+                    .read_to_end(&mut compressed)?;
+            }
+        }
+        Ok(compressed)
+    }
+
+    pub fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decompressed = Vec::new();
+        match self {
+            Codec::None => decompressed.extend_from_slice(data),
+            Codec::Xz => {
+                XzDecoder::new(data).read_to_end(&mut decompressed)?;
+            }
+            Codec::Zstd => decompressed = zstd::decode_all(data)?,
+            Codec::Gzip => {
+                flate2::read::GzDecoder::new(data).read_to_end(&mut decompressed)?;
+            }
+            Codec::Brotli => {
+                brotli::Decompressor::new(data, 4096).read_to_end(&mut decompressed)?;
+            }
+        }
+        Ok(decompressed)
+    }
+}
+
+/// How the (compressed) bytes of a file are written as text
+#[derive(
+    Serialize, Deserialize, JsonSchema, Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    Base85,
+    Base64,
+    /// Text as is, only for uncompressed contents
+    Utf8,
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Encoding::Base85 => "base85",
+            Encoding::Base64 => "base64",
+            Encoding::Utf8 => "utf8",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl Encoding {
+    /// Encoding of contents compressed with `codec` when the node does not tell
+    pub fn default_for(codec: Codec) -> Self {
+        match codec {
+            Codec::None => Encoding::Utf8,
+            _ => Encoding::Base85,
+        }
+    }
+
+    /// Encodes bytes as text, invalid UTF-8 being replaced with `utf8`
+    pub fn encode(self, data: &[u8]) -> String {
+        match self {
+            Encoding::Base85 => base85::encode(data),
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD.encode(data),
+            Encoding::Utf8 => String::from_utf8_lossy(data).to_string(),
+        }
+    }
+
+    pub fn decode(self, content: &str) -> io::Result<Vec<u8>> {
+        match self {
+            Encoding::Base85 => base85::decode(content).map_err(io::Error::other),
+            Encoding::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(content.trim())
+                .map_err(io::Error::other),
+            Encoding::Utf8 => Ok(content.as_bytes().to_vec()),
+        }
+    }
+}
+
+/// Encoding asked for when exporting, `auto` choosing it for each file (see [`Storage::auto`])
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EncodingChoice {
+    Auto,
+    Base85,
+    Base64,
+    Utf8,
+}
+
+/// Valid UTF-8 files up to this size are always stored as text by `auto`
+pub const AUTO_TEXT_SIZE: usize = 4096;
+/// `auto` only compresses a file when its encoded content shrinks to this fraction of what it
+/// would be without compression
+pub const AUTO_MAX_RATIO: f64 = 0.75;
+
+/// How the content of a file is stored in a kraglefile: compressed with `codec` (at `level`, the
+/// codec default when `None`), then written as text with `encoding`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Storage {
+    pub codec: Codec,
+    pub level: Option<u32>,
+    pub encoding: Encoding,
+    /// Chooses between text, compressed and uncompressed base85 for each file
+    pub auto: bool,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Storage {
+            codec: Codec::None,
+            level: None,
+            encoding: Encoding::Utf8,
+            auto: false,
+        }
+    }
+}
+
+impl Storage {
+    /// Checks that the level is supported by the codec and that compressed contents are not
+    /// written as `utf8`
+    pub fn new(codec: Codec, level: Option<u32>, encoding: Option<Encoding>) -> io::Result<Self> {
+        let encoding = encoding.unwrap_or(Encoding::default_for(codec));
+        if codec != Codec::None && encoding == Encoding::Utf8 {
+            return Err(io::Error::other(format!(
+                "{} compressed contents cannot be encoded as utf8",
+                codec
+            )));
+        }
+        if level.is_some() && codec == Codec::None {
+            return Err(io::Error::other("a compression level requires a codec"));
+        }
+        if let Some(level) = level
+            && !codec.levels().contains(&level)
+        {
+            return Err(io::Error::other(format!(
+                "level {} is not supported by codec {} (expected {} to {})",
+                level,
+                codec,
+                codec.levels().start(),
+                codec.levels().end()
+            )));
+        }
+        Ok(Storage {
+            codec,
+            level,
+            encoding,
+            auto: false,
+        })
+    }
+
+    /// Storage choosing the encoding of each file from its content: valid UTF-8 stays readable
+    /// unless it is larger than [`AUTO_TEXT_SIZE`] and compresses below [`AUTO_MAX_RATIO`],
+    /// other contents are base85-encoded, compressed under the same condition. `codec` defaults
+    /// to xz, `none` never compressing.
+    pub fn auto(codec: Option<Codec>, level: Option<u32>) -> io::Result<Self> {
+        Ok(Storage {
+            auto: true,
+            ..Storage::new(codec.unwrap_or(Codec::Xz), level, None)?
+        })
+    }
+
+    /// Storage of a file node from its fields, `is_compressed` being the deprecated xz flag
+    pub fn of_node(
+        codec: Option<Codec>,
+        encoding: Option<Encoding>,
+        is_compressed: Option<bool>,
+    ) -> Self {
+        let codec = codec.unwrap_or(if is_compressed.unwrap_or(false) {
+            Codec::Xz
+        } else {
+            Codec::None
+        });
+        Storage {
+            codec,
+            level: None,
+            encoding: encoding.unwrap_or(Encoding::default_for(codec)),
+            auto: false,
+        }
+    }
+
+    /// The same storage, compressing with xz when it has no codec (files compressed by the
+    /// export configuration, binary files)
+    pub fn compressed(self) -> Self {
+        if self.is_compressed() {
+            return Storage {
+                auto: false,
+                ..self
+            };
+        }
+        Storage {
+            codec: Codec::Xz,
+            level: None,
+            encoding: match self.encoding {
+                Encoding::Utf8 => Encoding::Base85,
+                encoding => encoding,
+            },
+            auto: false,
+        }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        self.codec != Codec::None
+    }
+
+    /// Fields written in a file node: the codec, and the encoding when it is not the default one
+    pub fn fields(self) -> (Option<Codec>, Option<Encoding>) {
+        (
+            Some(self.codec),
+            (self.encoding != Encoding::default_for(self.codec)).then_some(self.encoding),
+        )
+    }
+
+    pub fn encode(self, data: &[u8]) -> io::Result<String> {
+        let level = self.level.unwrap_or(self.codec.default_level());
+        Ok(self.encoding.encode(&self.codec.compress(data, level)?))
+    }
+
+    pub fn decode(self, content: &str) -> io::Result<Vec<u8>> {
+        self.codec.decompress(&self.encoding.decode(content)?)
+    }
+
+    /// Encodes the content of a file, returning the storage actually used: the one chosen by
+    /// `auto`, or the compressed one when text storage would lose invalid UTF-8
+    pub fn choose(self, data: &[u8]) -> io::Result<(Self, String)> {
+        let is_text = std::str::from_utf8(data).is_ok();
+        if !self.auto {
+            let storage = if self.encoding == Encoding::Utf8 && !is_text {
+                self.compressed()
+            } else {
+                self
+            };
+            return Ok((storage, storage.encode(data)?));
+        }
+
+        let uncompressed = Storage {
+            encoding: if is_text {
+                Encoding::Utf8
+            } else {
+                Encoding::Base85
+            },
+            ..Storage::default()
+        };
+        if (is_text && data.len() <= AUTO_TEXT_SIZE) || !self.is_compressed() {
+            return Ok((uncompressed, uncompressed.encode(data)?));
+        }
+        let plain = uncompressed.encode(data)?;
+        let compressed = Storage {
+            auto: false,
+            ..self
+        };
+        let content = compressed.encode(data)?;
+        if (content.len() as f64) <= plain.len() as f64 * AUTO_MAX_RATIO {
+            Ok((compressed, content))
+        } else {
+            Ok((uncompressed, plain))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = b"\x89PNG\xff\x00kragle kragle kragle kragle kragle".to_vec();
+        for codec in [
+            Codec::None,
+            Codec::Xz,
+            Codec::Zstd,
+            Codec::Gzip,
+            Codec::Brotli,
+        ] {
+            for encoding in [Encoding::Base85, Encoding::Base64] {
+                for level in [None, Some(*codec.levels().end())] {
+                    let Ok(storage) = Storage::new(codec, level, Some(encoding)) else {
+                        assert_eq!((codec, level.is_some()), (Codec::None, true));
+                        continue;
+                    };
+                    let content = storage.encode(&data).unwrap();
+                    assert_eq!(storage.decode(&content).unwrap(), data, "{}", codec);
+                }
+            }
+        }
+
+        // Contents written by previous versions
+        let storage = Storage::of_node(None, None, Some(true));
+        assert_eq!(
+            storage
+                .decode(
+                    "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRFqGBW@GVZ|H=L?|OL00A2V3op44AGY&-0RR9100dcD"
+                )
+                .unwrap(),
+            b"123"
+        );
+        assert_eq!(Storage::of_node(None, None, None), Storage::default());
+    }
+
+    #[test]
+    fn test_choose() {
+        let auto = Storage::auto(None, None).unwrap();
+        let choose = |data: &[u8]| {
+            let (storage, content) = auto.choose(data).unwrap();
+            assert_eq!(storage.decode(&content).unwrap(), data);
+            (storage.codec, storage.encoding)
+        };
+        // Pseudo-random text and bytes do not compress
+        let mut seed = 42u32;
+        let random: Vec<u8> = (0..AUTO_TEXT_SIZE * 2)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let random_text: Vec<u8> = random.iter().map(|byte| b'a' + byte % 26).collect();
+
+        assert_eq!(choose(b"kragle\n"), (Codec::None, Encoding::Utf8));
+        assert_eq!(
+            choose(&b"kragle\n".repeat(1000)),
+            (Codec::Xz, Encoding::Base85)
+        );
+        assert_eq!(choose(&random_text), (Codec::None, Encoding::Utf8));
+        assert_eq!(choose(b"\x89PNG\xff\x00"), (Codec::None, Encoding::Base85));
+        assert_eq!(
+            choose(&b"\x89PNG\xff\x00".repeat(1000)),
+            (Codec::Xz, Encoding::Base85)
+        );
+        assert_eq!(choose(&random), (Codec::None, Encoding::Base85));
+
+        // Invalid UTF-8 is never stored as text
+        let (storage, _) = Storage::default().choose(b"\x89PNG\xff\x00").unwrap();
+        assert_eq!(storage, Storage::default().compressed());
+    }
+
+    #[test]
+    fn test_storage() {
+        assert!(Storage::new(Codec::Zstd, Some(23), None).is_err());
+        assert!(Storage::new(Codec::None, Some(1), None).is_err());
+        assert!(Storage::new(Codec::Gzip, None, Some(Encoding::Utf8)).is_err());
+
+        let storage = Storage::new(Codec::Zstd, Some(19), None).unwrap();
+        assert_eq!(storage.encoding, Encoding::Base85);
+        assert_eq!(storage.fields(), (Some(Codec::Zstd), None));
+        assert_eq!(storage.compressed(), storage);
+
+        let storage = Storage::new(Codec::None, None, Some(Encoding::Base64)).unwrap();
+        assert_eq!(
+            storage.fields(),
+            (Some(Codec::None), Some(Encoding::Base64))
+        );
+        assert_eq!(
+            storage.compressed(),
+            Storage::new(Codec::Xz, None, Some(Encoding::Base64)).unwrap()
+        );
+        assert_eq!(
+            Storage::default().compressed(),
+            Storage::of_node(None, None, Some(true))
+        );
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+/// Names of the export configuration file, looked up at the root of the exported folder
+pub const CONFIG_FILES: [&str; 2] = ["kragle.toml", ".kragle.yaml"];
+
+/// How a folder is exported, read from `kragle.toml` or `.kragle.yaml`.
+///
+/// Paths are globs relative to the exported folder (e.g. `docs/**`, `*.log`). A rule matching a
+/// directory applies to everything below it.
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ExportConfig {
+    /// Description of the root node
+    pub description: Option<String>,
+    /// Dependencies of the root node
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    /// Files exported with `is_optional: true`
+    #[serde(default)]
+    pub optional: Vec<String>,
+    /// Files and directories left out of the export
+    #[serde(default)]
+    pub ignore: Vec<String>,
+    /// Files stored compressed, in addition to binary files and `--compressed`
+    #[serde(default)]
+    pub compressed: Vec<String>,
+    /// Values stored in the `parameters` field of the root node
+    #[serde(default)]
+    pub parameters: BTreeMap<String, Value>,
+}
+
+impl ExportConfig {
+    /// Reads the configuration of a folder, the default one when it has no configuration file
+    pub fn load(folder: &Path) -> io::Result<Self> {
+        let found: Vec<&str> = CONFIG_FILES
+            .into_iter()
+            .filter(|name| folder.join(name).is_file())
+            .collect();
+        let name = match found[..] {
+            [] => return Ok(ExportConfig::default()),
+            [name] => name,
+            _ => {
+                return Err(io::Error::other(format!(
+                    "{} has both {} and {}, keep only one of them",
+                    folder.display(),
+                    CONFIG_FILES[0],
+                    CONFIG_FILES[1]
+                )));
+            }
+        };
+
+        let path = folder.join(name);
+        let data = fs::read_to_string(&path)?;
+        let invalid = |message: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), message),
+            )
+        };
+        let config: ExportConfig = if name.ends_with(".toml") {
+            toml::from_str(&data).map_err(|e| invalid(e.to_string()))?
+        } else {
+            serde_yml::from_str(&data).map_err(|e| invalid(e.to_string()))?
+        };
+
+        for pattern in config
+            .optional
+            .iter()
+            .chain(&config.ignore)
+            .chain(&config.compressed)
+        {
+            glob::Pattern::new(pattern)
+                .map_err(|e| invalid(format!("invalid glob {:?}: {}", pattern, e)))?;
+        }
+        Ok(config)
+    }
+
+    /// Whether a path is left out of the export, the configuration file itself always being
+    pub fn is_ignored(&self, relative: &Path) -> bool {
+        CONFIG_FILES.iter().any(|name| relative == Path::new(name))
+            || matches_any(&self.ignore, relative)
+    }
+
+    pub fn is_optional(&self, relative: &Path) -> bool {
+        matches_any(&self.optional, relative)
+    }
+
+    pub fn is_compressed(&self, relative: &Path) -> bool {
+        matches_any(&self.compressed, relative)
+    }
+}
+
+/// Whether a path or one of its parents matches one of the globs
+fn matches_any(patterns: &[String], relative: &Path) -> bool {
+    patterns
+        .iter()
+        .filter_map(|pattern| glob::Pattern::new(pattern).ok())
+        .any(|pattern| {
+            relative
+                .ancestors()
+                .filter(|path| !path.as_os_str().is_empty())
+                .any(|path| pattern.matches_path(path))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load() {
+        let temp_dir = TempDir::new().unwrap();
+        assert_eq!(
+            ExportConfig::load(temp_dir.path()).unwrap(),
+            ExportConfig::default()
+        );
+
+        fs::write(
+            temp_dir.path().join("kragle.toml"),
+            r#"
+description = "Rust library"
+dependencies = ["kragle-repo"]
+optional = ["README.md", "docs"]
+ignore = ["target", "*.log"]
+compressed = ["assets/**"]
+
+[parameters]
+project_name = "kragle"
+msrv = 1.85
+"#,
+        )
+        .unwrap();
+        let config = ExportConfig::load(temp_dir.path()).unwrap();
+        assert_eq!(config.description.as_deref(), Some("Rust library"));
+        assert_eq!(config.parameters["project_name"], "kragle");
+        assert!(config.is_ignored(Path::new("kragle.toml")));
+        assert!(config.is_ignored(Path::new("target/debug/kragle")));
+        assert!(config.is_ignored(Path::new("logs/run.log")));
+        assert!(!config.is_ignored(Path::new("src/target.rs")));
+        assert!(config.is_optional(Path::new("docs/index.md")));
+        assert!(config.is_compressed(Path::new("assets/logo.svg")));
+        assert!(!config.is_compressed(Path::new("README.md")));
+
+        fs::write(temp_dir.path().join(".kragle.yaml"), "ignore: [target]\n").unwrap();
+        assert!(ExportConfig::load(temp_dir.path()).is_err());
+
+        fs::remove_file(temp_dir.path().join("kragle.toml")).unwrap();
+        fs::write(temp_dir.path().join(".kragle.yaml"), "ignored: [target]\n").unwrap();
+        assert!(ExportConfig::load(temp_dir.path()).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::anyhow;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::cache::get_uri;
+
+/// JSON Schema describing the content of a structured file (JSON, YAML or TOML)
+#[derive(Serialize, Deserialize, JsonSchema, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum ContentSchema {
+    /// Schema retrieved from an http(s) URI (through the cache) or a local path
+    Uri(String),
+    /// Schema written directly in the kraglefile
+    Inline(Value),
+}
+
+impl ContentSchema {
+    /// Returns the schema document
+    pub fn resolve(&self) -> anyhow::Result<Value> {
+        match self {
+            ContentSchema::Inline(schema) => Ok(schema.clone()),
+            ContentSchema::Uri(uri) => {
+                let data = if uri.starts_with("http://") || uri.starts_with("https://") {
+                    get_uri(uri)?
+                } else {
+                    fs::read(uri)?
+                };
+                // YAML is a superset of JSON
+                Ok(serde_yml::from_slice(&data)?)
+            }
+        }
+    }
+
+    /// Validates the content of a file, returns the list of violations
+    pub fn check(&self, file_path: &Path, data: &[u8]) -> anyhow::Result<Vec<String>> {
+        let schema = self.resolve()?;
+        let validator = jsonschema::validator_for(&schema)
+            .map_err(|e| anyhow!("invalid JSON Schema: {}", e))?;
+
+        let instance = match parse_structured(file_path, data) {
+            Ok(instance) => instance,
+            Err(e) => return Ok(vec![format!("cannot parse file: {}", e)]),
+        };
+
+        Ok(validator
+            .iter_errors(&instance)
+            .map(|error| {
+                let instance_path = error.instance_path().to_string();
+                format!(
+                    "{}: {}",
+                    if instance_path.is_empty() {
+                        "/"
+                    } else {
+                        &instance_path
+                    },
+                    error
+                )
+            })
+            .collect())
+    }
+}
+
+/// Parses a JSON, YAML or TOML file (chosen from its extension) into a JSON value
+pub fn parse_structured(file_path: &Path, data: &[u8]) -> anyhow::Result<Value> {
+    let text = std::str::from_utf8(data)?;
+    match file_path.extension().and_then(|e| e.to_str()) {
+        Some("json") => Ok(serde_json::from_str(text)?),
+        Some("yaml") | Some("yml") => Ok(serde_yml::from_str(text)?),
+        Some("toml") => Ok(toml::from_str(text)?),
+        _ => Err(anyhow!(
+            "unsupported file type (expected .json, .yaml, .yml or .toml)"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_structured() {
+        let value = parse_structured(
+            Path::new("Cargo.toml"),
+            b"[package]\nname = \"kragle\"\nedition = \"2024\"\n",
+        )
+        .unwrap();
+        assert_eq!(value["package"]["edition"], json!("2024"));
+
+        let value = parse_structured(Path::new("package.json"), b"{\"license\": \"MIT\"}").unwrap();
+        assert_eq!(value["license"], json!("MIT"));
+
+        assert!(parse_structured(Path::new("README.md"), b"# kragle").is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let schema = ContentSchema::Inline(json!({
+            "type": "object",
+            "required": ["package"],
+            "properties": {
+                "package": {
+                    "type": "object",
+                    "required": ["edition"],
+                    "properties": {"edition": {"enum": ["2021", "2024"]}}
+                }
+            }
+        }));
+
+        let path = Path::new("Cargo.toml");
+        assert!(
+            schema
+                .check(path, b"[package]\nedition = \"2024\"\n")
+                .unwrap()
+                .is_empty()
+        );
+
+        let violations = schema
+            .check(path, b"[package]\nedition = \"2018\"\n")
+            .unwrap();
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("/package/edition: "));
+
+        let violations = schema.check(path, b"[dependencies]\n").unwrap();
+        assert_eq!(violations.len(), 1);
+        assert!(violations[0].starts_with("/: "));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let schema: ContentSchema = serde_yml::from_str("https://example.com/schema.json").unwrap();
+        assert_eq!(
+            schema,
+            ContentSchema::Uri("https://example.com/schema.json".to_string())
+        );
+
+        let schema: ContentSchema = serde_yml::from_str("{type: object}").unwrap();
+        assert_eq!(schema, ContentSchema::Inline(json!({"type": "object"})));
+    }
+}
@@ -0,0 +1,139 @@
+use std::io;
+use std::path::Path;
+
+use crate::codec::{Encoding, Storage};
+use crate::diff::has_content;
+use crate::repo::Repo;
+
+/// Files whose storage was changed by [`set_storage`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Recoded {
+    pub changed: Vec<String>,
+    /// Binary files left as they were, their content not being valid UTF-8
+    pub skipped: Vec<String>,
+}
+
+/// Stores the content of the files of a structure with another codec and encoding, only those
+/// matching `only` (a glob on their path, e.g. `static/**`) when given.
+///
+/// Files without content and forbidden files are left untouched.
+pub fn set_storage(repo: &mut Repo, storage: Storage, only: Option<&str>) -> io::Result<Recoded> {
+    let only = match only {
+        Some(only) => Some(glob::Pattern::new(only).map_err(io::Error::other)?),
+        None => None,
+    };
+    let mut recoded = Recoded::default();
+    recode(repo, Path::new(""), storage, only.as_ref(), &mut recoded)?;
+    Ok(recoded)
+}
+
+fn recode(
+    node: &mut Repo,
+    parent: &Path,
+    storage: Storage,
+    only: Option<&glob::Pattern>,
+    recoded: &mut Recoded,
+) -> io::Result<()> {
+    let Some(name) = node.name() else {
+        return Ok(());
+    };
+    let path = Repo::normalized(&parent.join(name));
+
+    match node {
+        Repo::Directory { children, .. } => {
+            for child in children {
+                recode(child, &path, storage, only, recoded)?;
+            }
+        }
+        Repo::File { .. }
+            if has_content(node)
+                && !node.is_forbidden()
+                && only.is_none_or(|only| only.matches_path(&path)) =>
+        {
+            let data = node.decoded()?;
+            let display = path.to_string_lossy().replace('\\', "/");
+            if storage.encoding == Encoding::Utf8 && std::str::from_utf8(&data).is_err() {
+                recoded.skipped.push(display);
+                return Ok(());
+            }
+            if node.storage().is_some_and(|current| {
+                (current.codec, current.encoding) != (storage.codec, storage.encoding)
+            }) {
+                node.set_content(&data, storage)?;
+                recoded.changed.push(display);
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Codec;
+
+    #[test]
+    fn test_set_compressed() {
+        let mut repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: README.md
+    content: "kragle\n"
+  - type: directory
+    name: static
+    children:
+      - type: file
+        name: robots.txt
+        content: "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRFqGBW@GVZ|H=L?|OL00A2V3op44AGY&-0RR9100dcD"
+        is_compressed: true
+      - type: file
+        name: logo.png
+        content: "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRPwP)<kx00000kk324dQrFx00ABb!7rp8AGY&-0RR9100dcD"
+        is_compressed: true
+  - type: file
+    name: .env
+    forbidden: true
+"#,
+        )
+        .unwrap();
+        let logo = repo.find("static/logo.png").unwrap().decoded().unwrap();
+
+        let recoded = set_storage(&mut repo, Storage::default(), None).unwrap();
+        assert_eq!(recoded.changed, vec!["static/robots.txt"]);
+        assert_eq!(recoded.skipped, vec!["static/logo.png"]);
+        assert!(matches!(
+            repo.find("static/robots.txt").unwrap(),
+            Repo::File { content, codec: Some(Codec::None), is_compressed: None, .. } if content == "123"
+        ));
+        assert_eq!(
+            repo.find("static/logo.png").unwrap().decoded().unwrap(),
+            logo
+        );
+
+        let zstd = Storage::new(Codec::Zstd, Some(19), Some(Encoding::Base64)).unwrap();
+        let recoded = set_storage(&mut repo, zstd, Some("static/**")).unwrap();
+        assert_eq!(
+            recoded.changed,
+            vec!["static/robots.txt", "static/logo.png"]
+        );
+        assert_eq!(
+            repo.find("static/robots.txt").unwrap().decoded().unwrap(),
+            b"123"
+        );
+        assert_eq!(
+            repo.find("static/logo.png").unwrap().storage(),
+            Some(Storage {
+                level: None,
+                ..zstd
+            })
+        );
+        assert!(matches!(
+            repo.find("README.md").unwrap(),
+            Repo::File { codec: None, .. }
+        ));
+    }
+}
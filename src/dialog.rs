@@ -1,4 +1,4 @@
-use std::io::Write;
+use std::io::{self, Write};
 
 use console::Style;
 use terminal_size::{Width, terminal_size};
@@ -8,6 +8,7 @@ pub enum Status {
     Ok,
     Failed,
     Warning,
+    Info,
 }
 
 impl Status {
@@ -17,6 +18,7 @@ pub fn to_string(&self) -> &'static str {
             Self::Ok => "[OK]",
             Self::Failed => "[FAILED]",
             Self::Warning => "[WAR]",
+            Self::Info => "[INFO]",
         }
     }
     pub fn colorized(&self) -> String {
@@ -25,6 +27,7 @@ pub fn colorized(&self) -> String {
             Self::Ok => Style::new().green().apply_to("[OK]").to_string(),
             Self::Failed => Style::new().red().apply_to("[FAILED]").to_string(),
             Self::Warning => Style::new().yellow().apply_to("[WAR]").to_string(),
+            Self::Info => Style::new().blue().apply_to("[INFO]").to_string(),
         }
     }
 }
@@ -93,6 +96,18 @@ pub fn spinner(&mut self) {
     }
 }
 
+/// Asks a yes/no question on stdin, answering no by default
+pub fn confirm(question: &str) -> io::Result<bool> {
+    print!("{} [y/N] ", question);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -102,6 +117,7 @@ fn test_status_to_string() {
         assert_eq!(Status::Unknown.to_string(), "[?]");
         assert_eq!(Status::Ok.to_string(), "[OK]");
         assert_eq!(Status::Failed.to_string(), "[FAILED]");
+        assert_eq!(Status::Info.to_string(), "[INFO]");
     }
 
     #[test]
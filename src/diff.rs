@@ -0,0 +1,649 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use console::Style;
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use serde::Serialize;
+use serde_json::Value;
+use similar::TextDiff;
+
+use crate::repo::Repo;
+
+/// Number of unchanged lines shown around each change
+const CONTEXT_RADIUS: usize = 3;
+
+/// Compares the files of a structure (`a/`) with the files of a folder (`b/`) as a unified diff.
+///
+/// Files only described by checks (neither content nor md5), patterns and forbidden nodes are
+/// ignored on both sides, as well as the `.git` directory of the folder. With `patch`, the output
+/// has git headers and can be given to `git apply`.
+pub fn diff_folder(repo: &Repo, folder: &Path, patch: bool) -> io::Result<String> {
+    let mut expected = BTreeMap::new();
+    for (path, node) in repo.files() {
+        if has_content(node) && !node.is_forbidden() {
+            expected.insert(path, node.decoded()?);
+        }
+    }
+
+    let mut actual = BTreeMap::new();
+    read_folder(folder, Path::new(""), &mut actual)?;
+    let ignored: Vec<(PathBuf, &Repo)> = repo
+        .nodes()
+        .into_iter()
+        .filter(|(_, node)| match node {
+            Repo::File { .. } => !has_content(node) || node.is_forbidden(),
+            Repo::Directory { .. } => node.is_forbidden(),
+            Repo::Pattern { .. } => true,
+            Repo::None {} => false,
+        })
+        .collect();
+    actual.retain(|path, _| {
+        !ignored
+            .iter()
+            .any(|(node_path, node)| covers(node_path, node, path))
+    });
+
+    let paths: BTreeSet<&PathBuf> = expected.keys().chain(actual.keys()).collect();
+    let mut diff = String::new();
+    for path in paths {
+        let file_diff = file_diff(
+            &display_path(path),
+            expected.get(path).map(Vec::as_slice),
+            actual.get(path).map(Vec::as_slice),
+            patch,
+        );
+        if patch {
+            diff.push_str(&file_diff);
+        } else {
+            diff.push_str(&colorized(&file_diff));
+        }
+    }
+    Ok(diff)
+}
+
+/// Old and new value of a property
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Change<T> {
+    pub old: T,
+    pub new: T,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// Difference between the two versions of a node
+#[derive(Serialize, Debug)]
+pub struct NodeDiff {
+    pub path: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<Change<Option<u64>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub md5: Option<Change<Option<String>>>,
+    /// Changes of the other fields of the node, by field name
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub metadata: BTreeMap<String, Change<Value>>,
+    /// Unified diff of the decoded content
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+}
+
+/// Compares two structures node by node, sorted by path
+pub fn diff_repos(old: &Repo, new: &Repo) -> io::Result<Vec<NodeDiff>> {
+    let old_nodes: BTreeMap<PathBuf, &Repo> = old.nodes().into_iter().collect();
+    let new_nodes: BTreeMap<PathBuf, &Repo> = new.nodes().into_iter().collect();
+    let paths: BTreeSet<&PathBuf> = old_nodes.keys().chain(new_nodes.keys()).collect();
+
+    let mut diffs = Vec::new();
+    for path in paths {
+        let display = match display_path(path) {
+            root if root.is_empty() => ".".to_string(),
+            display => display,
+        };
+        let (old_node, new_node) = match (old_nodes.get(path), new_nodes.get(path)) {
+            (Some(old_node), Some(new_node)) => (*old_node, *new_node),
+            (old_node, _) => {
+                diffs.push(NodeDiff {
+                    path: display,
+                    status: match old_node {
+                        Some(_) => Status::Removed,
+                        None => Status::Added,
+                    },
+                    size: None,
+                    md5: None,
+                    metadata: BTreeMap::new(),
+                    content: None,
+                });
+                continue;
+            }
+        };
+
+        let mut diff = NodeDiff {
+            path: display,
+            status: Status::Modified,
+            size: None,
+            md5: None,
+            metadata: BTreeMap::new(),
+            content: None,
+        };
+
+        let old_metadata = metadata(old_node)?;
+        let new_metadata = metadata(new_node)?;
+        let keys: BTreeSet<&String> = old_metadata.keys().chain(new_metadata.keys()).collect();
+        for key in keys {
+            let old_value = old_metadata.get(key).cloned().unwrap_or_default();
+            let new_value = new_metadata.get(key).cloned().unwrap_or_default();
+            if old_value != new_value {
+                diff.metadata.insert(
+                    key.clone(),
+                    Change {
+                        old: old_value,
+                        new: new_value,
+                    },
+                );
+            }
+        }
+
+        if let (
+            Repo::File {
+                original_size: old_size,
+                original_md5: old_md5,
+                ..
+            },
+            Repo::File {
+                original_size: new_size,
+                original_md5: new_md5,
+                ..
+            },
+        ) = (old_node, new_node)
+        {
+            if old_size != new_size {
+                diff.size = Some(Change {
+                    old: *old_size,
+                    new: *new_size,
+                });
+            }
+            if old_md5 != new_md5 {
+                diff.md5 = Some(Change {
+                    old: old_md5.clone(),
+                    new: new_md5.clone(),
+                });
+            }
+            let content = file_diff(
+                &diff.path,
+                Some(&old_node.decoded()?),
+                Some(&new_node.decoded()?),
+                false,
+            );
+            if !content.is_empty() {
+                diff.content = Some(content);
+            }
+        }
+
+        if diff.size.is_some()
+            || diff.md5.is_some()
+            || !diff.metadata.is_empty()
+            || diff.content.is_some()
+        {
+            diffs.push(diff);
+        }
+    }
+    Ok(diffs)
+}
+
+/// Fields of a node, except its children, content, size and md5
+fn metadata(node: &Repo) -> io::Result<BTreeMap<String, Value>> {
+    let mut fields = match serde_json::to_value(node)? {
+        Value::Object(fields) => fields,
+        _ => return Ok(BTreeMap::new()),
+    };
+    for key in ["children", "content", "original_size", "original_md5"] {
+        fields.remove(key);
+    }
+    Ok(fields.into_iter().collect())
+}
+
+/// Renders the differences between two structures as text
+pub fn render_repo_diff(diffs: &[NodeDiff]) -> String {
+    let mut text = String::new();
+    for diff in diffs {
+        match diff.status {
+            Status::Added => text.push_str(&format!(
+                "{}\n",
+                Style::new()
+                    .green()
+                    .apply_to(format!("added:    {}", diff.path))
+            )),
+            Status::Removed => text.push_str(&format!(
+                "{}\n",
+                Style::new()
+                    .red()
+                    .apply_to(format!("removed:  {}", diff.path))
+            )),
+            Status::Modified => {
+                text.push_str(&format!(
+                    "{}\n",
+                    Style::new()
+                        .yellow()
+                        .apply_to(format!("modified: {}", diff.path))
+                ));
+                if let Some(size) = &diff.size {
+                    text.push_str(&format!(
+                        "    original_size: {} -> {}\n",
+                        show(&size.old),
+                        show(&size.new)
+                    ));
+                }
+                if let Some(md5) = &diff.md5 {
+                    text.push_str(&format!(
+                        "    original_md5: {} -> {}\n",
+                        show(&md5.old),
+                        show(&md5.new)
+                    ));
+                }
+                for (key, change) in &diff.metadata {
+                    text.push_str(&format!("    {}: {} -> {}\n", key, change.old, change.new));
+                }
+                if let Some(content) = &diff.content {
+                    text.push_str(&colorized(content));
+                }
+            }
+        }
+    }
+    text
+}
+
+/// Renders a value as JSON (`null` for a missing value)
+fn show<T: Serialize>(value: &T) -> String {
+    serde_json::to_string(value).unwrap_or_default()
+}
+
+/// Whether a file node embeds the content of the file
+pub(crate) fn has_content(node: &Repo) -> bool {
+    matches!(node, Repo::File { content, original_md5, .. } if !content.is_empty() || original_md5.is_some())
+}
+
+/// Whether a path of the folder is the path of a node or below it, patterns covering the entries
+/// they match
+fn covers(node_path: &Path, node: &Repo, path: &Path) -> bool {
+    let Repo::Pattern { name, .. } = node else {
+        return path.starts_with(node_path);
+    };
+    let parent = node_path.parent().unwrap_or(Path::new(""));
+    let entry = path
+        .strip_prefix(parent)
+        .ok()
+        .and_then(|relative| relative.components().next());
+    match (entry, glob::Pattern::new(name)) {
+        (Some(entry), Ok(pattern)) => pattern.matches(&entry.as_os_str().to_string_lossy()),
+        _ => false,
+    }
+}
+
+/// Reads the content of every file of a folder, indexed by path relative to the folder
+fn read_folder(
+    folder: &Path,
+    relative: &Path,
+    files: &mut BTreeMap<PathBuf, Vec<u8>>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(folder.join(relative))? {
+        let entry = entry?;
+        let path = relative.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                read_folder(folder, &path, files)?;
+            }
+        } else if file_type.is_file() {
+            files.insert(path, fs::read(entry.path())?);
+        }
+    }
+    Ok(())
+}
+
+/// Path with `/` separators, as expected by diff tools
+pub fn display_path(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn as_text(data: &[u8]) -> Option<&str> {
+    std::str::from_utf8(data)
+        .ok()
+        .filter(|text| !text.contains('\0'))
+}
+
+/// Unified diff of a single file, `None` meaning that the file does not exist on that side.
+///
+/// With `patch`, binary changes are written as git binary patches rather than a single line.
+pub fn file_diff(path: &str, old: Option<&[u8]>, new: Option<&[u8]>, patch: bool) -> String {
+    if old == new {
+        return String::new();
+    }
+
+    let mut diff = String::new();
+    if patch {
+        diff.push_str(&format!("diff --git a/{} b/{}\n", path, path));
+        match (old, new) {
+            (None, Some(_)) => diff.push_str("new file mode 100644\n"),
+            (Some(_), None) => diff.push_str("deleted file mode 100644\n"),
+            _ => {}
+        }
+    }
+
+    let old_name = match old {
+        Some(_) => format!("a/{}", path),
+        None => "/dev/null".to_string(),
+    };
+    let new_name = match new {
+        Some(_) => format!("b/{}", path),
+        None => "/dev/null".to_string(),
+    };
+
+    match (
+        as_text(old.unwrap_or_default()),
+        as_text(new.unwrap_or_default()),
+    ) {
+        (Some(old_text), Some(new_text)) => {
+            let text_diff = TextDiff::from_lines(old_text, new_text);
+            diff.push_str(
+                &text_diff
+                    .unified_diff()
+                    .context_radius(CONTEXT_RADIUS)
+                    .header(&old_name, &new_name)
+                    .to_string(),
+            );
+        }
+        _ if patch => diff.push_str(&binary_patch(old, new)),
+        _ => diff.push_str(&format!(
+            "Binary files {} and {} differ\n",
+            old_name, new_name
+        )),
+    }
+    diff
+}
+
+/// Characters of the base85 encoding of git binary patches
+const GIT_BASE85: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Body of a git binary patch, from its `index` line: the new content as a forward hunk and the
+/// old content as a reverse hunk, both as literals
+fn binary_patch(old: Option<&[u8]>, new: Option<&[u8]>) -> String {
+    let mode = match (old, new) {
+        (Some(_), Some(_)) => " 100644",
+        _ => "",
+    };
+    format!(
+        "index {}..{}{}\nGIT binary patch\n{}\n{}\n",
+        blob_id(old),
+        blob_id(new),
+        mode,
+        binary_literal(new.unwrap_or_default()),
+        binary_literal(old.unwrap_or_default())
+    )
+}
+
+/// Object id of a git blob, zeros for a missing file
+fn blob_id(data: Option<&[u8]>) -> String {
+    let Some(data) = data else {
+        return "0".repeat(40);
+    };
+    let mut hasher = sha1_smol::Sha1::new();
+    hasher.update(format!("blob {}\0", data.len()).as_bytes());
+    hasher.update(data);
+    hasher.digest().to_string()
+}
+
+/// `literal` hunk of a git binary patch: the zlib-compressed data in base85 lines of up to 52
+/// bytes, each prefixed with its length (`A`-`Z` for 1-26, `a`-`z` for 27-52)
+fn binary_literal(data: &[u8]) -> String {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::fast());
+    // Writing to a Vec cannot fail
+    let _ = encoder.write_all(data);
+    let deflated = encoder.finish().unwrap_or_default();
+
+    let mut literal = format!("literal {}\n", data.len());
+    for line in deflated.chunks(52) {
+        literal.push(match line.len() {
+            length @ 1..=26 => (b'A' + length as u8 - 1) as char,
+            length => (b'a' + length as u8 - 27) as char,
+        });
+        for group in line.chunks(4) {
+            let mut bytes = [0; 4];
+            bytes[..group.len()].copy_from_slice(group);
+            let mut value = u32::from_be_bytes(bytes);
+            let mut chars = [0; 5];
+            for char in chars.iter_mut().rev() {
+                *char = GIT_BASE85[(value % 85) as usize];
+                value /= 85;
+            }
+            literal.push_str(&String::from_utf8_lossy(&chars));
+        }
+        literal.push('\n');
+    }
+    literal
+}
+
+/// Colors the lines of a unified diff (only when the output supports it)
+pub fn colorized(diff: &str) -> String {
+    diff.split_inclusive('\n')
+        .map(|line| {
+            let style = if line.starts_with("---") || line.starts_with("+++") {
+                Style::new().bold()
+            } else if line.starts_with('-') {
+                Style::new().red()
+            } else if line.starts_with('+') {
+                Style::new().green()
+            } else if line.starts_with("@@") {
+                Style::new().cyan()
+            } else {
+                return line.to_string();
+            };
+            match line.strip_suffix('\n') {
+                Some(line) => format!("{}\n", style.apply_to(line)),
+                None => style.apply_to(line).to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_file_diff() {
+        assert_eq!(file_diff("same.txt", Some(b"a\n"), Some(b"a\n"), true), "");
+
+        assert_eq!(
+            file_diff("src/lib.rs", Some(b"a\nb\n"), Some(b"a\nc\n"), true),
+            "diff --git a/src/lib.rs b/src/lib.rs\n\
+             --- a/src/lib.rs\n\
+             +++ b/src/lib.rs\n\
+             @@ -1,2 +1,2 @@\n \
+             a\n\
+             -b\n\
+             +c\n"
+        );
+
+        assert_eq!(
+            file_diff("new.txt", None, Some(b"hello\n"), true),
+            "diff --git a/new.txt b/new.txt\n\
+             new file mode 100644\n\
+             --- /dev/null\n\
+             +++ b/new.txt\n\
+             @@ -0,0 +1 @@\n\
+             +hello\n"
+        );
+
+        assert_eq!(
+            file_diff("icon.png", Some(b"\x89PNG\0"), None, false),
+            "Binary files a/icon.png and /dev/null differ\n"
+        );
+
+        // Binary changes are git binary patches, with the same literals as `git diff --binary`
+        let binary = file_diff("icon.png", Some(b"\x89PNG\0"), Some(b""), true);
+        assert_eq!(
+            binary,
+            "diff --git a/icon.png b/icon.png\n\
+             index 0a7e2a167b940e0e8fabe53845eb444e4ca1f771..e69de29bb2d1d6434b8b29ae775ad8c2e48c5391 100644\n\
+             GIT binary patch\n\
+             literal 0\n\
+             HcmV?d00001\n\n\
+             literal 5\n\
+             McmeAS@N;JX00n9RZvX%Q\n\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let folder = temp_dir.path();
+        fs::create_dir_all(folder.join("src")).unwrap();
+        fs::write(folder.join("src/lib.rs"), "pub mod repo;\n").unwrap();
+        fs::write(folder.join("added.txt"), "added\n").unwrap();
+        fs::write(folder.join("LICENSE"), "MIT\n").unwrap();
+        fs::write(folder.join("debug.log"), "debug\n").unwrap();
+        fs::create_dir_all(folder.join("target/debug")).unwrap();
+        fs::write(folder.join("target/debug/kragle"), "\0").unwrap();
+
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: missing.txt
+    content: "missing\n"
+  - type: file
+    name: LICENSE
+    check: exists
+    assertions:
+      - contains: MIT
+  - type: pattern
+    name: "*.log"
+    forbidden: true
+  - type: directory
+    name: target
+    forbidden: true
+  - type: directory
+    name: src
+    children:
+      - type: file
+        name: lib.rs
+        content: "pub mod repo;\n"
+"#,
+        )
+        .unwrap();
+
+        let diff = diff_folder(&repo, folder, true).unwrap();
+        assert_eq!(
+            diff,
+            "diff --git a/added.txt b/added.txt\n\
+             new file mode 100644\n\
+             --- /dev/null\n\
+             +++ b/added.txt\n\
+             @@ -0,0 +1 @@\n\
+             +added\n\
+             diff --git a/missing.txt b/missing.txt\n\
+             deleted file mode 100644\n\
+             --- a/missing.txt\n\
+             +++ /dev/null\n\
+             @@ -1 +0,0 @@\n\
+             -missing\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_repos() {
+        let old: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: removed.txt
+    content: "removed\n"
+  - type: file
+    name: lib.rs
+    content: "pub mod repo;\n"
+    original_size: 14
+    is_optional: false
+  - type: file
+    name: same.txt
+    content: "same\n"
+"#,
+        )
+        .unwrap();
+        let new: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+description: Rust project
+children:
+  - type: file
+    name: lib.rs
+    content: "pub mod diff;\n"
+    original_size: 14
+    is_optional: true
+  - type: file
+    name: same.txt
+    content: "same\n"
+  - type: pattern
+    name: "*.rs"
+"#,
+        )
+        .unwrap();
+
+        let diffs = diff_repos(&old, &new).unwrap();
+        let summary: Vec<(&str, Status)> = diffs
+            .iter()
+            .map(|diff| (diff.path.as_str(), diff.status))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (".", Status::Modified),
+                ("*.rs", Status::Added),
+                ("lib.rs", Status::Modified),
+                ("removed.txt", Status::Removed),
+            ]
+        );
+
+        assert_eq!(
+            diffs[0].metadata["description"],
+            Change {
+                old: Value::Null,
+                new: Value::from("Rust project")
+            }
+        );
+        let lib = &diffs[2];
+        assert!(lib.size.is_none());
+        assert_eq!(
+            lib.metadata["is_optional"],
+            Change {
+                old: Value::from(false),
+                new: Value::from(true)
+            }
+        );
+        assert!(
+            lib.content
+                .as_ref()
+                .unwrap()
+                .contains("-pub mod repo;\n+pub mod diff;\n")
+        );
+
+        let json = serde_json::to_value(&diffs).unwrap();
+        assert_eq!(json[3]["status"], "removed");
+        assert!(render_repo_diff(&diffs).contains("is_optional: false -> true"));
+    }
+}
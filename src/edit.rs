@@ -0,0 +1,898 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use serde_json::Value;
+
+use crate::format::Format;
+use crate::located::{Located, Position};
+use crate::repo::{Repo, STDIO, Source};
+
+/// Change made in place to a kraglefile
+#[derive(Debug)]
+pub enum Edit {
+    /// Adds a node, named after the last component of `at`
+    Add {
+        at: PathBuf,
+        node: Repo,
+    },
+    Remove {
+        at: PathBuf,
+    },
+    /// Moves and/or renames a node
+    Move {
+        from: PathBuf,
+        to: PathBuf,
+    },
+    /// Replaces the content of a file, updating its size and md5
+    SetContent {
+        at: PathBuf,
+        data: Vec<u8>,
+    },
+}
+
+/// Applies an edit to a local kraglefile.
+///
+/// YAML kraglefiles are edited line by line: the lines of the other nodes, comments included, are
+/// left untouched. JSON and TOML kraglefiles are written again as a whole.
+pub fn apply(uri: &String, edit: Edit) -> io::Result<()> {
+    let source = Repo::fetch(uri)?;
+    if source.uri == STDIO {
+        return Err(io::Error::other(
+            "Cannot modify a kraglefile read from stdin",
+        ));
+    }
+    if Repo::is_remote(&source.uri) {
+        return Err(io::Error::other(format!(
+            "Cannot modify remote kraglefile {}",
+            source.uri
+        )));
+    }
+
+    let mut repo = Repo::parse(&source)?;
+    let edit = edit_repo(&mut repo, edit)?;
+    if source.format != Format::Yaml {
+        return repo.save_as(&source.uri, source.format);
+    }
+
+    let mut text = YamlText::new(&String::from_utf8_lossy(&source.data));
+    text.apply(&edit, &repo)?;
+    write_checked(source, &text)
+}
+
+/// Writes a refreshed structure (see `refresh::refresh`) to the local kraglefile it was parsed
+/// from.
+///
+/// Like `apply`, YAML kraglefiles only get the lines of the removed, added and updated nodes
+/// rewritten.
+pub fn save_refreshed(source: Source, old: &Repo, new: &Repo) -> io::Result<()> {
+    if source.format != Format::Yaml {
+        return new.save_as(&source.uri, source.format);
+    }
+
+    let mut text = YamlText::new(&String::from_utf8_lossy(&source.data));
+    for edit in refresh_edits(old, new)? {
+        text.apply(&edit, new)?;
+    }
+    write_checked(source, &text)
+}
+
+fn write_checked(source: Source, text: &YamlText) -> io::Result<()> {
+    let edited = Source {
+        data: text.to_string().into_bytes(),
+        ..source
+    };
+    // Never write a kraglefile that does not load anymore
+    Repo::parse(&edited)?;
+    fs::write(&edited.uri, &edited.data)
+}
+
+/// Edits turning a structure into its refreshed version: the directories and files removed or
+/// replaced by a node of another type, the files whose content changed and the added nodes
+fn refresh_edits(old: &Repo, new: &Repo) -> io::Result<Vec<Edit>> {
+    let nodes = |repo| -> Vec<(PathBuf, &Repo)> {
+        Repo::nodes(repo)
+            .into_iter()
+            .filter(|(_, node)| matches!(node, Repo::Directory { .. } | Repo::File { .. }))
+            .collect()
+    };
+    let (old_nodes, new_nodes) = (nodes(old), nodes(new));
+    let old_paths: BTreeMap<&PathBuf, &Repo> =
+        old_nodes.iter().map(|(path, node)| (path, *node)).collect();
+    let new_paths: BTreeMap<&PathBuf, &Repo> =
+        new_nodes.iter().map(|(path, node)| (path, *node)).collect();
+    let same_type = |a: &Repo, b: &Repo| std::mem::discriminant(a) == std::mem::discriminant(b);
+
+    let mut edits = Vec::new();
+    let mut removed: Vec<&PathBuf> = Vec::new();
+    for (path, node) in &old_nodes {
+        if removed.iter().any(|parent| path.starts_with(parent)) {
+            continue;
+        }
+        match new_paths.get(path) {
+            Some(new_node) if same_type(node, new_node) => {
+                if matches!(node, Repo::File { .. })
+                    && serde_json::to_value(node)? != serde_json::to_value(new_node)?
+                {
+                    edits.push(Edit::SetContent {
+                        at: path.clone(),
+                        data: new_node.decoded()?,
+                    });
+                }
+            }
+            _ => {
+                edits.push(Edit::Remove { at: path.clone() });
+                removed.push(path);
+            }
+        }
+    }
+
+    let mut added: Vec<&PathBuf> = Vec::new();
+    for (path, node) in &new_nodes {
+        if added.iter().any(|parent| path.starts_with(parent)) {
+            continue;
+        }
+        if old_paths
+            .get(path)
+            .is_some_and(|old_node| same_type(old_node, node))
+        {
+            continue;
+        }
+        edits.push(Edit::Add {
+            at: path.clone(),
+            node: serde_json::from_value(serde_json::to_value(node)?)?,
+        });
+        added.push(path);
+    }
+    Ok(edits)
+}
+
+/// Opens the content of a file in `$VISUAL` or `$EDITOR` (`vi` by default) and returns the edited
+/// content
+pub fn open_in_editor(data: &[u8], file_name: &str) -> io::Result<Vec<u8>> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let program = words
+        .next()
+        .ok_or_else(|| io::Error::other("Empty $EDITOR"))?;
+
+    // Keep the file name so that the editor picks the right syntax
+    let folder = env::temp_dir().join(format!("kragle-edit-{}", std::process::id()));
+    fs::create_dir_all(&folder)?;
+    let path = folder.join(file_name);
+    fs::write(&path, data)?;
+
+    let status = Command::new(program).args(words).arg(&path).status();
+    let edited = fs::read(&path);
+    fs::remove_dir_all(&folder)?;
+
+    if !status?.success() {
+        return Err(io::Error::other(format!("{} exited with an error", editor)));
+    }
+    edited
+}
+
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found in the kraglefile", path.display()),
+    )
+}
+
+/// Splits a path into the path of its parent and its name
+fn split(path: &Path) -> io::Result<(PathBuf, String)> {
+    let path = Repo::normalized(path);
+    match path.file_name() {
+        Some(name) => Ok((
+            path.parent().unwrap_or(Path::new("")).to_path_buf(),
+            name.to_string_lossy().to_string(),
+        )),
+        None => Err(io::Error::other(
+            "The root of a kraglefile cannot be changed",
+        )),
+    }
+}
+
+/// Names of the components of a path below the root of a structure
+fn components(root_name: &str, path: &Path) -> io::Result<Vec<String>> {
+    let root = Repo::normalized(Path::new(root_name));
+    let path = Repo::normalized(path);
+    let relative = path.strip_prefix(&root).map_err(|_| not_found(&path))?;
+    Ok(relative
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().to_string())
+        .collect())
+}
+
+fn set_name(node: &mut Repo, new_name: String) {
+    if let Repo::Directory { name, .. } | Repo::File { name, .. } | Repo::Pattern { name, .. } =
+        node
+    {
+        *name = new_name;
+    }
+}
+
+/// Directory or file node at a path, patterns excluded
+fn find_mut<'a>(repo: &'a mut Repo, path: &Path) -> io::Result<&'a mut Repo> {
+    let mut node = repo;
+    for name in components(node.name().unwrap_or(""), path)? {
+        let Repo::Directory { children, .. } = node else {
+            return Err(not_found(path));
+        };
+        node = children
+            .iter_mut()
+            .find(|child| {
+                child.name() == Some(name.as_str()) && !matches!(child, Repo::Pattern { .. })
+            })
+            .ok_or_else(|| not_found(path))?;
+    }
+    Ok(node)
+}
+
+fn children_mut<'a>(repo: &'a mut Repo, path: &Path) -> io::Result<&'a mut Vec<Repo>> {
+    match find_mut(repo, path)? {
+        Repo::Directory { children, .. } => Ok(children),
+        _ => Err(io::Error::other(format!(
+            "{} is not a directory",
+            path.display()
+        ))),
+    }
+}
+
+/// Applies an edit to the structure, returning it with the defaults filled in (e.g. the new name
+/// of an added node, the new content of a file)
+fn edit_repo(repo: &mut Repo, edit: Edit) -> io::Result<Edit> {
+    match edit {
+        Edit::Add { at, mut node } => {
+            let (parent, name) = split(&at)?;
+            let children = children_mut(repo, &parent)?;
+            if children
+                .iter()
+                .any(|child| child.name() == Some(name.as_str()))
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists in the kraglefile", at.display()),
+                ));
+            }
+            set_name(&mut node, name);
+            children.push(node);
+            Ok(Edit::Add {
+                at,
+                node: serde_json::from_value(serde_json::to_value(children.last())?)?,
+            })
+        }
+        Edit::Remove { at } => {
+            let (parent, name) = split(&at)?;
+            let children = children_mut(repo, &parent)?;
+            let index = children
+                .iter()
+                .position(|child| {
+                    child.name() == Some(name.as_str()) && !matches!(child, Repo::Pattern { .. })
+                })
+                .ok_or_else(|| not_found(&at))?;
+            children.remove(index);
+            Ok(Edit::Remove { at })
+        }
+        Edit::Move { from, to } => {
+            let (from_parent, _) = split(&from)?;
+            let (to_parent, to_name) = split(&to)?;
+            if Repo::normalized(&to_parent).starts_with(Repo::normalized(&from)) {
+                return Err(io::Error::other(format!(
+                    "Cannot move {} into itself",
+                    from.display()
+                )));
+            }
+            find_mut(repo, &from)?;
+            children_mut(repo, &to_parent)?;
+            if find_mut(repo, &to).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists in the kraglefile", to.display()),
+                ));
+            }
+
+            let (_, from_name) = split(&from)?;
+            let children = children_mut(repo, &from_parent)?;
+            let index = children
+                .iter()
+                .position(|child| {
+                    child.name() == Some(from_name.as_str())
+                        && !matches!(child, Repo::Pattern { .. })
+                })
+                .ok_or_else(|| not_found(&from))?;
+            let mut node = children.remove(index);
+            set_name(&mut node, to_name);
+            children_mut(repo, &to_parent)?.push(node);
+            Ok(Edit::Move { from, to })
+        }
+        Edit::SetContent { at, data } => {
+            let node = find_mut(repo, &at)?;
+            let Some(storage) = node.storage() else {
+                return Err(io::Error::other(format!("{} is not a file", at.display())));
+            };
+            // Content that is not valid UTF-8 switches text nodes to a compressed storage
+            node.set_content(&data, storage)?;
+            let Repo::File {
+                original_size,
+                original_md5,
+                ..
+            } = node
+            else {
+                unreachable!("file node");
+            };
+            *original_size = Some(data.len() as u64);
+            *original_md5 = Some(format!("{:x}", md5::compute(&data)));
+            Ok(Edit::SetContent { at, data })
+        }
+    }
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// Scalar formatted as a YAML value (quoted when needed)
+fn yaml_scalar(value: &str) -> io::Result<String> {
+    Ok(serde_yml::to_string(value)
+        .map_err(io::Error::other)?
+        .trim_end()
+        .to_string())
+}
+
+/// Removes the unset fields of a node and of its children
+fn without_nulls(value: &mut serde_yml::Value) {
+    match value {
+        serde_yml::Value::Mapping(fields) => {
+            fields.retain(|_, field| !field.is_null());
+            fields.values_mut().for_each(without_nulls);
+        }
+        serde_yml::Value::Sequence(items) => items.iter_mut().for_each(without_nulls),
+        serde_yml::Value::Tagged(tagged) => without_nulls(&mut tagged.value),
+        _ => {}
+    }
+}
+
+/// Node of a YAML kraglefile located in its lines
+struct Item<'a> {
+    node: &'a Located,
+    /// First line of the node (0-based), the one holding the `-` of sequence items
+    start: usize,
+    /// Line following the last non-blank line of the node
+    end: usize,
+    /// Column of the `-` of sequence items (0-based), `None` for the root
+    dash: Option<usize>,
+}
+
+/// YAML kraglefile edited line by line
+struct YamlText {
+    lines: Vec<String>,
+}
+
+impl YamlText {
+    fn new(text: &str) -> Self {
+        YamlText {
+            lines: text.lines().map(str::to_string).collect(),
+        }
+    }
+
+    fn parse(&self) -> io::Result<Located> {
+        Located::parse(&self.to_string()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Line following the last non-blank line indented more than `column`, from `start`
+    fn block_end(&self, start: usize, column: usize) -> usize {
+        let mut end = start + 1;
+        for (index, line) in self.lines.iter().enumerate().skip(start + 1) {
+            if is_blank(line) {
+                continue;
+            }
+            if indent(line) <= column {
+                break;
+            }
+            end = index + 1;
+        }
+        end
+    }
+
+    fn item<'a>(&self, node: &'a Located, is_root: bool) -> io::Result<Item<'a>> {
+        let unsupported =
+            || io::Error::other("Unsupported YAML layout, only block style can be edited");
+        let position = Self::start(node).ok_or_else(unsupported)?;
+        let start = position.line - 1;
+        if is_root {
+            return Ok(Item {
+                node,
+                start,
+                end: self.lines.len(),
+                dash: None,
+            });
+        }
+
+        let prefix = self.lines[start]
+            .get(..position.column - 1)
+            .ok_or_else(unsupported)?
+            .trim_end();
+        if prefix.trim() != "-" {
+            return Err(unsupported());
+        }
+        let dash = prefix.len() - 1;
+        Ok(Item {
+            node,
+            start,
+            end: self.block_end(start, dash),
+            dash: Some(dash),
+        })
+    }
+
+    /// Locates the directory or file node at a path
+    fn find<'a>(&self, root: &'a Located, path: &Path) -> io::Result<Item<'a>> {
+        let root_name = root.get("name").and_then(Located::as_str).unwrap_or("");
+        let mut node = root;
+        let components = components(root_name, path)?;
+        for name in &components {
+            let Some(Located::Sequence(_, children)) = node.get("children") else {
+                return Err(not_found(path));
+            };
+            node = children
+                .iter()
+                .find(|child| {
+                    child.get("name").and_then(Located::as_str) == Some(name.as_str())
+                        && child.get("type").and_then(Located::as_str) != Some("pattern")
+                })
+                .ok_or_else(|| not_found(path))?;
+        }
+        self.item(node, components.is_empty())
+    }
+
+    /// Position of the first key of a mapping, where its block starts
+    fn start(node: &Located) -> Option<Position> {
+        match node {
+            Located::Mapping(_, entries) => entries.first().map(|(_, position, _)| *position),
+            _ => None,
+        }
+    }
+
+    /// Position of a key of a mapping
+    fn key(node: &Located, key: &str) -> Option<Position> {
+        match node {
+            Located::Mapping(_, entries) => entries
+                .iter()
+                .find(|(name, ..)| name == key)
+                .map(|(_, position, _)| *position),
+            _ => None,
+        }
+    }
+
+    /// Replaces the line of a key (and the lines of its value) by `key: value`
+    fn replace_entry(&mut self, position: Position, lines: Vec<String>) {
+        let start = position.line - 1;
+        let column = position.column - 1;
+        let end = self.block_end(start, column);
+        let prefix = self.lines[start][..column].to_string();
+
+        let replacement = lines.into_iter().enumerate().map(|(index, line)| {
+            if index == 0 {
+                format!("{}{}", prefix, line)
+            } else if line.is_empty() {
+                line
+            } else {
+                format!("{}{}", " ".repeat(column), line)
+            }
+        });
+        self.lines
+            .splice(start..end, replacement.collect::<Vec<_>>());
+    }
+
+    /// Sets a field of the node at a path, keeping its other fields
+    fn set_field(&mut self, path: &Path, key: &str, value: &Value) -> io::Result<()> {
+        let mut entry = serde_json::Map::new();
+        entry.insert(key.to_string(), value.clone());
+        let yaml = serde_yml::to_string(&entry).map_err(io::Error::other)?;
+        let lines: Vec<String> = yaml.lines().map(str::to_string).collect();
+
+        let root = self.parse()?;
+        let item = self.find(&root, path)?;
+        match Self::key(item.node, key) {
+            Some(position) => self.replace_entry(position, lines),
+            None => {
+                let column = Self::start(item.node).map_or(0, |start| start.column - 1);
+                let indented = lines.into_iter().map(|line| {
+                    if line.is_empty() {
+                        line
+                    } else {
+                        format!("{}{}", " ".repeat(column), line)
+                    }
+                });
+                self.lines
+                    .splice(item.end..item.end, indented.collect::<Vec<_>>());
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes a field of the node at a path, if it is set
+    fn remove_field(&mut self, path: &Path, key: &str) -> io::Result<()> {
+        let root = self.parse()?;
+        let item = self.find(&root, path)?;
+        let Some(position) = Self::key(item.node, key) else {
+            return Ok(());
+        };
+        let start = position.line - 1;
+        let column = position.column - 1;
+        if !self.lines[start][..column].trim().is_empty() {
+            // First field of a sequence item, after its `-`
+            return self.set_field(path, key, &Value::Null);
+        }
+        let end = self.block_end(start, column);
+        self.lines.drain(start..end);
+        Ok(())
+    }
+
+    /// Removes the lines of a node, returning them without the indentation of its `-`
+    fn remove(&mut self, path: &Path) -> io::Result<Vec<String>> {
+        let root = self.parse()?;
+        let item = self.find(&root, path)?;
+        let dash = item
+            .dash
+            .ok_or_else(|| io::Error::other("The root cannot be removed"))?;
+        let (start, end) = (item.start, item.end);
+        let removed: Vec<String> = self
+            .lines
+            .drain(start..end)
+            .map(|line| line.get(dash..).unwrap_or("").to_string())
+            .collect();
+
+        // A directory without children keeps an empty list rather than a null
+        let (parent, _) = split(path)?;
+        let root = self.parse()?;
+        let parent = self.find(&root, &parent)?;
+        if let Some(Located::Scalar(..)) = parent.node.get("children")
+            && let Some(position) = Self::key(parent.node, "children")
+        {
+            self.replace_entry(position, vec!["children: []".to_string()]);
+        }
+        Ok(removed)
+    }
+
+    /// Appends a sequence item (whose `-` is at column 0) to the children of a directory
+    fn insert(&mut self, parent: &Path, block: Vec<String>) -> io::Result<()> {
+        let root = self.parse()?;
+        let item = self.find(&root, parent)?;
+        let column = Self::start(item.node).map_or(0, |start| start.column - 1);
+
+        let (at, dash) = match item.node.get("children") {
+            Some(Located::Sequence(_, children)) if !children.is_empty() => {
+                let first = self.item(&children[0], false)?;
+                let last = self.item(&children[children.len() - 1], false)?;
+                (last.end, first.dash.unwrap_or(column))
+            }
+            Some(_) => {
+                let position = Self::key(item.node, "children").ok_or_else(|| {
+                    io::Error::other("Unsupported YAML layout, only block style can be edited")
+                })?;
+                self.replace_entry(position, vec!["children:".to_string()]);
+                (position.line, column + 2)
+            }
+            None => {
+                self.lines
+                    .insert(item.end, format!("{}children:", " ".repeat(column)));
+                (item.end + 1, column + 2)
+            }
+        };
+
+        let indented = block.into_iter().map(|line| {
+            if line.is_empty() {
+                line
+            } else {
+                format!("{}{}", " ".repeat(dash), line)
+            }
+        });
+        self.lines.splice(at..at, indented.collect::<Vec<_>>());
+        Ok(())
+    }
+
+    /// Replaces the value of the `name` key of the single item of a block
+    fn rename_block(block: Vec<String>, name: &str) -> io::Result<Vec<String>> {
+        let mut text = YamlText { lines: block };
+        let located = text.parse()?;
+        let Located::Sequence(_, items) = &located else {
+            return Err(io::Error::other("Unsupported YAML layout"));
+        };
+        let position = items
+            .first()
+            .and_then(|item| Self::key(item, "name"))
+            .ok_or_else(|| io::Error::other("Node without a name"))?;
+        text.replace_entry(position, vec![format!("name: {}", yaml_scalar(name)?)]);
+        Ok(text.lines)
+    }
+
+    fn apply(&mut self, edit: &Edit, repo: &Repo) -> io::Result<()> {
+        match edit {
+            Edit::Add { at, node } => {
+                let (parent, _) = split(at)?;
+                let mut value = serde_yml::to_value(node).map_err(io::Error::other)?;
+                without_nulls(&mut value);
+                let yaml = serde_yml::to_string(&value).map_err(io::Error::other)?;
+                let block = yaml
+                    .lines()
+                    .enumerate()
+                    .map(|(index, line)| match (index, line.is_empty()) {
+                        (0, _) => format!("- {}", line),
+                        (_, true) => String::new(),
+                        (_, false) => format!("  {}", line),
+                    })
+                    .collect();
+                self.insert(&parent, block)
+            }
+            Edit::Remove { at } => self.remove(at).map(|_| ()),
+            Edit::Move { from, to } => {
+                let (to_parent, to_name) = split(to)?;
+                let block = self.remove(from)?;
+                let block = Self::rename_block(block, &to_name)?;
+                self.insert(&to_parent, block)
+            }
+            Edit::SetContent { at, .. } => {
+                let Repo::File {
+                    content,
+                    original_size,
+                    original_md5,
+                    codec,
+                    encoding,
+                    ..
+                } = repo.find(at)?
+                else {
+                    return Err(io::Error::other(format!("{} is not a file", at.display())));
+                };
+                self.set_field(at, "content", &Value::from(content.as_str()))?;
+                self.set_field(at, "original_size", &serde_json::to_value(original_size)?)?;
+                self.set_field(at, "original_md5", &serde_json::to_value(original_md5)?)?;
+                self.set_field(at, "codec", &serde_json::to_value(codec)?)?;
+                match encoding {
+                    Some(encoding) => {
+                        self.set_field(at, "encoding", &serde_json::to_value(encoding)?)?
+                    }
+                    None => self.remove_field(at, "encoding")?,
+                }
+                // Replaced by `codec` when the content is set
+                self.remove_field(at, "is_compressed")
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for YamlText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for line in &self.lines {
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codec::Storage;
+    use crate::lint::lint_repo;
+    use tempfile::TempDir;
+
+    const KRAGLEFILE: &str = r#"# Template for small projects
+type: directory
+name: "."
+children:
+  # Keep the readme first
+  - type: file
+    name: README.md
+    content: |
+      # kragle
+    is_optional: false
+  - type: directory
+    name: src
+    description: Sources # inline comment
+    children:
+      - type: file
+        name: lib.rs
+        content: "pub mod repo;\n"
+  - type: directory
+    name: tests
+    children: []
+"#;
+
+    fn setup() -> (TempDir, String) {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("kraglefile.yaml");
+        fs::write(&path, KRAGLEFILE).unwrap();
+        (temp_dir, path.to_string_lossy().to_string())
+    }
+
+    fn file(content: &str) -> Repo {
+        serde_json::from_value(serde_json::json!({
+            "type": "file",
+            "name": "",
+            "content": content,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_add() {
+        let (_temp_dir, path) = setup();
+        apply(
+            &path,
+            Edit::Add {
+                at: PathBuf::from("tests/cli.rs"),
+                node: file("#[test]\nfn cli() {}\n"),
+            },
+        )
+        .unwrap();
+        apply(
+            &path,
+            Edit::Add {
+                at: PathBuf::from("src/main.rs"),
+                node: file("fn main() {}\n"),
+            },
+        )
+        .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# Template for small projects\n"));
+        assert!(text.contains("  # Keep the readme first\n"));
+        assert!(text.contains("    description: Sources # inline comment\n"));
+        assert!(text.contains(
+            "        content: \"pub mod repo;\\n\"\n      - type: file\n        name: main.rs\n"
+        ));
+        assert!(text.contains("    children:\n      - type: file\n        name: cli.rs\n"));
+
+        let repo = Repo::load(&path).unwrap();
+        assert_eq!(
+            repo.find("tests/cli.rs").unwrap().decoded().unwrap(),
+            b"#[test]\nfn cli() {}\n"
+        );
+        assert!(
+            apply(
+                &path,
+                Edit::Add {
+                    at: PathBuf::from("src/lib.rs"),
+                    node: file("")
+                }
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_remove_and_move() {
+        let (_temp_dir, path) = setup();
+        apply(
+            &path,
+            Edit::Move {
+                from: PathBuf::from("README.md"),
+                to: PathBuf::from("tests/README.txt"),
+            },
+        )
+        .unwrap();
+        apply(
+            &path,
+            Edit::Remove {
+                at: PathBuf::from("src/lib.rs"),
+            },
+        )
+        .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("    description: Sources # inline comment\n    children: []\n"));
+        assert!(text.contains(
+            "    children:\n      - type: file\n        name: README.txt\n        content: |\n          # kragle\n        is_optional: false\n"
+        ));
+
+        let repo = Repo::load(&path).unwrap();
+        assert!(repo.find("README.md").is_err());
+        assert_eq!(
+            repo.find("tests/README.txt").unwrap().decoded().unwrap(),
+            b"# kragle\n"
+        );
+        assert!(
+            apply(
+                &path,
+                Edit::Move {
+                    from: PathBuf::from("tests"),
+                    to: PathBuf::from("tests/unit")
+                }
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_set_content() {
+        let (_temp_dir, path) = setup();
+        apply(
+            &path,
+            Edit::SetContent {
+                at: PathBuf::from("README.md"),
+                data: b"# kragle\n\nFolder structures as YAML\n".to_vec(),
+            },
+        )
+        .unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains(
+            "    name: README.md\n    content: |\n      # kragle\n\n      Folder structures as YAML\n    is_optional: false\n    original_size: 36\n    original_md5: "
+        ));
+        assert!(text.contains("  # Keep the readme first\n"));
+
+        let repo = Repo::load(&path).unwrap();
+        let node = repo.find("README.md").unwrap();
+        assert_eq!(
+            node.decoded().unwrap(),
+            b"# kragle\n\nFolder structures as YAML\n"
+        );
+        assert!(matches!(
+            node,
+            Repo::File {
+                original_size: Some(36),
+                ..
+            }
+        ));
+
+        // Binary content is compressed instead of being stored as lossy text
+        apply(
+            &path,
+            Edit::SetContent {
+                at: PathBuf::from("src/lib.rs"),
+                data: b"\xff\xfebin".to_vec(),
+            },
+        )
+        .unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.contains("        codec: xz\n"));
+        let repo = Repo::load(&path).unwrap();
+        let node = repo.find("src/lib.rs").unwrap();
+        assert_eq!(node.decoded().unwrap(), b"\xff\xfebin");
+        assert!(lint_repo(&repo, None).is_empty());
+    }
+
+    #[test]
+    fn test_save_refreshed() {
+        let (temp_dir, path) = setup();
+        let folder = temp_dir.path().join("folder");
+        fs::create_dir_all(folder.join("src")).unwrap();
+        fs::write(folder.join("README.md"), "# kragle\n").unwrap();
+        fs::write(folder.join("src/lib.rs"), "pub mod edit;\n").unwrap();
+        fs::write(folder.join("src/main.rs"), "fn main() {}\n").unwrap();
+        // A directory replaced by a file
+        fs::write(folder.join("tests"), "none\n").unwrap();
+
+        let source = Repo::fetch(&path).unwrap();
+        let old = Repo::parse(&source).unwrap();
+        let mut repo = Repo::parse(&source).unwrap();
+        crate::refresh::refresh(&mut repo, &folder, Storage::default()).unwrap();
+        save_refreshed(source, &old, &repo).unwrap();
+
+        let text = fs::read_to_string(&path).unwrap();
+        assert!(text.starts_with("# Template for small projects\n"));
+        assert!(text.contains("  # Keep the readme first\n"));
+        assert!(text.contains("    description: Sources # inline comment\n"));
+        let saved = Repo::load(&path).unwrap();
+        assert_eq!(
+            serde_json::to_value(&saved).unwrap(),
+            serde_json::to_value(&repo).unwrap()
+        );
+        assert_eq!(
+            saved.find("src/lib.rs").unwrap().decoded().unwrap(),
+            b"pub mod edit;\n"
+        );
+        assert!(saved.find("src/main.rs").is_ok());
+        assert!(matches!(saved.find("tests").unwrap(), Repo::File { .. }));
+    }
+}
@@ -0,0 +1,177 @@
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::pack::{MAGIC, Pack};
+
+/// Syntax of a kraglefile or a manifest
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Json,
+    Yaml,
+    Toml,
+    /// Indexed binary container, see [`crate::pack::Pack`]
+    Pack,
+}
+
+impl Format {
+    /// Format matching the extension of a path or an URI, `None` for other extensions
+    pub fn from_extension(path: &str) -> Option<Self> {
+        // Ignore the query of URIs (e.g. `?raw=true`)
+        let path = path.split('?').next().unwrap_or(path);
+        match Path::new(path).extension().and_then(|e| e.to_str()) {
+            Some("json") => Some(Format::Json),
+            Some("yaml") | Some("yml") => Some(Format::Yaml),
+            Some("toml") => Some(Format::Toml),
+            Some("kpack") => Some(Format::Pack),
+            _ => None,
+        }
+    }
+
+    /// Format matching the media type of an HTTP `Content-Type` header
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        match media_type.to_ascii_lowercase().as_str() {
+            "application/json" => Some(Format::Json),
+            "application/yaml" | "application/x-yaml" | "text/yaml" | "text/x-yaml" => {
+                Some(Format::Yaml)
+            }
+            "application/toml" | "text/toml" | "text/x-toml" => Some(Format::Toml),
+            media_type if media_type.ends_with("+json") => Some(Format::Json),
+            media_type if media_type.ends_with("+yaml") => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format of a source from its content: a pack when it starts with the magic of
+    /// packs, JSON when it parses as JSON, TOML when it parses as a TOML table, YAML otherwise
+    pub fn sniff(data: &[u8]) -> Self {
+        if data.starts_with(MAGIC) {
+            return Format::Pack;
+        }
+        let text = String::from_utf8_lossy(data);
+        let text = text.trim_start_matches('\u{feff}').trim_start();
+        if text.starts_with(['{', '['])
+            && serde_json::from_str::<serde::de::IgnoredAny>(text).is_ok()
+        {
+            Format::Json
+        } else if !text.is_empty() && toml::from_str::<toml::Table>(text).is_ok() {
+            Format::Toml
+        } else {
+            Format::Yaml
+        }
+    }
+
+    /// Whether positions can be read from the source with [`crate::located::Located`]
+    pub fn is_yaml_compatible(self) -> bool {
+        matches!(self, Format::Json | Format::Yaml)
+    }
+
+    pub fn parse<T: DeserializeOwned>(self, data: &[u8]) -> io::Result<T> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        match self {
+            Format::Json => serde_json::from_slice(data).map_err(|e| invalid(e.to_string())),
+            Format::Yaml => serde_yml::from_slice(data).map_err(|e| invalid(e.to_string())),
+            Format::Toml => {
+                let text = std::str::from_utf8(data).map_err(|e| invalid(e.to_string()))?;
+                toml::from_str(text).map_err(|e| invalid(e.to_string()))
+            }
+            Format::Pack => {
+                let repo = Pack::new(io::Cursor::new(data))?.into_repo()?;
+                Ok(serde_json::from_value(serde_json::to_value(repo)?)?)
+            }
+        }
+    }
+
+    /// Serializes a value, ending with a newline (packs are written with
+    /// [`crate::pack::write_pack`])
+    pub fn to_string<T: Serialize>(self, value: &T) -> io::Result<String> {
+        match self {
+            Format::Pack => Err(io::Error::other("kpack is a binary format")),
+            Format::Json => Ok(serde_json::to_string_pretty(value)? + "\n"),
+            Format::Yaml => serde_yml::to_string(value).map_err(io::Error::other),
+            Format::Toml => toml::to_string(value).map_err(io::Error::other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[test]
+    fn test_from_extension() {
+        assert_eq!(Format::from_extension("repo.json"), Some(Format::Json));
+        assert_eq!(Format::from_extension("./repo.yml"), Some(Format::Yaml));
+        assert_eq!(
+            Format::from_extension(
+                "https://github.com/Jxtopher/kragle/blob/main/kraglefile/a.toml?raw=true"
+            ),
+            Some(Format::Toml)
+        );
+        assert_eq!(Format::from_extension("repo.txt"), None);
+    }
+
+    #[test]
+    fn test_from_content_type() {
+        assert_eq!(
+            Format::from_content_type("application/json; charset=utf-8"),
+            Some(Format::Json)
+        );
+        assert_eq!(
+            Format::from_content_type("application/x-yaml"),
+            Some(Format::Yaml)
+        );
+        assert_eq!(Format::from_content_type("text/toml"), Some(Format::Toml));
+        assert_eq!(Format::from_content_type("text/plain; charset=utf-8"), None);
+    }
+
+    #[test]
+    fn test_sniff() {
+        assert_eq!(
+            Format::sniff(b"  {\"type\": \"directory\", \"name\": \".\"}"),
+            Format::Json
+        );
+        assert_eq!(
+            Format::sniff(b"[{\"name\": \"kragle-repo.yaml\"}]"),
+            Format::Json
+        );
+        assert_eq!(
+            Format::sniff(b"# kragle\ntype = \"directory\"\nname = \".\"\n"),
+            Format::Toml
+        );
+        assert_eq!(
+            Format::sniff(b"[[kraglefile]]\nname = \"kragle-repo.yaml\"\n"),
+            Format::Toml
+        );
+        assert_eq!(
+            Format::sniff(b"# kragle\ntype: directory\nname: .\n"),
+            Format::Yaml
+        );
+        assert_eq!(Format::sniff(b"- name: kragle-repo.yaml\n"), Format::Yaml);
+        assert_eq!(Format::sniff(b"{type: directory}"), Format::Yaml);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let value = json!({
+            "type": "directory",
+            "name": ".",
+            "children": [
+                {"type": "file", "name": "README.md", "content": "# kragle\n\nFolder structures\n"}
+            ]
+        });
+        for format in [Format::Json, Format::Yaml, Format::Toml] {
+            let text = format.to_string(&value).unwrap();
+            assert!(text.ends_with('\n'));
+            assert_eq!(format.parse::<Value>(text.as_bytes()).unwrap(), value);
+        }
+
+        let toml = Format::Toml.to_string(&value).unwrap();
+        assert!(toml.contains("[[children]]\n"));
+        assert!(toml.contains("content = \"\"\"\n# kragle\n\nFolder structures\n\"\"\"\n"));
+    }
+}
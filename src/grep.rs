@@ -0,0 +1,134 @@
+use std::fmt;
+use std::io;
+
+use console::Style;
+use regex::Regex;
+
+use crate::repo::Repo;
+
+/// Line of a file of a kraglefile matching a pattern
+#[derive(Debug, PartialEq, Eq)]
+pub struct Match {
+    /// Path of the file in the kraglefile
+    pub path: String,
+    /// Line number, starting at 1
+    pub line: usize,
+    pub text: String,
+    /// Byte ranges of the matches within `text`
+    pub ranges: Vec<(usize, usize)>,
+}
+
+impl Match {
+    /// Formats the match as `path:line:text`, highlighted when stdout is a terminal
+    pub fn colorized(&self, prefix: &str) -> String {
+        let mut text = String::new();
+        let mut last = 0;
+        for &(start, end) in &self.ranges {
+            text.push_str(&self.text[last..start]);
+            text.push_str(
+                &Style::new()
+                    .red()
+                    .bold()
+                    .apply_to(&self.text[start..end])
+                    .to_string(),
+            );
+            last = end;
+        }
+        text.push_str(&self.text[last..]);
+
+        format!(
+            "{}:{}:{}",
+            Style::new()
+                .magenta()
+                .apply_to(format!("{}{}", prefix, self.path)),
+            Style::new().green().apply_to(self.line),
+            text
+        )
+    }
+}
+
+impl fmt::Display for Match {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.text)
+    }
+}
+
+/// Searches the decoded content of the files of a structure, line by line.
+///
+/// Forbidden nodes and binary files (containing a NUL byte) are skipped.
+pub fn grep(repo: &Repo, regex: &Regex) -> io::Result<Vec<Match>> {
+    let mut matches = Vec::new();
+    for (path, node) in repo.files() {
+        if node.is_forbidden() {
+            continue;
+        }
+        let data = node.decoded()?;
+        if data.contains(&0) {
+            continue;
+        }
+
+        let content = String::from_utf8_lossy(&data);
+        for (index, line) in content.lines().enumerate() {
+            let ranges: Vec<(usize, usize)> = regex
+                .find_iter(line)
+                .map(|found| (found.start(), found.end()))
+                .collect();
+            if !ranges.is_empty() {
+                matches.push(Match {
+                    path: path.to_string_lossy().replace('\\', "/"),
+                    line: index + 1,
+                    text: line.to_string(),
+                    ranges,
+                });
+            }
+        }
+    }
+    Ok(matches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grep() {
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: directory
+    name: .github
+    children:
+      - type: file
+        name: ci.yml
+        content: "steps:\n  - uses: actions/checkout@v3\n  - uses: actions/cache@v4\n"
+  - type: file
+    name: release.yml
+    content: "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRFqGBW@GVZ|H=L?|OL00A2V3op44AGY&-0RR9100dcD"
+    is_compressed: true
+  - type: file
+    name: logo.png
+    content: "\0actions/checkout@v3"
+"#,
+        )
+        .unwrap();
+
+        let matches = grep(&repo, &Regex::new(r"actions/\w+@v3").unwrap()).unwrap();
+        assert_eq!(
+            matches.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![".github/ci.yml:2:  - uses: actions/checkout@v3"]
+        );
+        assert_eq!(matches[0].ranges, vec![(10, 29)]);
+
+        // Compressed contents are searched too
+        let matches = grep(&repo, &Regex::new("^12").unwrap()).unwrap();
+        assert_eq!(matches[0].to_string(), "release.yml:1:123");
+
+        console::set_colors_enabled(false);
+        assert_eq!(
+            matches[0].colorized("kragle-repo:"),
+            "kragle-repo:release.yml:1:123"
+        );
+    }
+}
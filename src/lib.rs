@@ -1,5 +1,23 @@
+pub mod assertion;
 pub mod cache;
+pub mod codec;
+pub mod config;
+pub mod content_schema;
+pub mod convert;
 pub mod dialog;
+pub mod diff;
+pub mod edit;
+pub mod format;
 pub mod globals;
+pub mod grep;
+pub mod lint;
+pub mod located;
 pub mod manifest;
+pub mod pack;
+pub mod refresh;
 pub mod repo;
+pub mod stats;
+pub mod tree;
+pub mod validation;
+pub mod version;
+pub mod watch;
@@ -0,0 +1,365 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::assertion::Assertion;
+use crate::located::{Located, Position, check_fields};
+use crate::repo::{Repo, STDIO};
+
+/// Authoring mistake found in a kraglefile
+#[derive(Debug, PartialEq, Eq)]
+pub struct Lint {
+    /// Path of the node in the structure
+    pub path: String,
+    /// Position of the mistake in the kraglefile, when known
+    pub position: Option<Position>,
+    pub message: String,
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(f, "{}: {} ({})", self.path, self.message, position),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+fn lint(path: &Path, message: String) -> Lint {
+    let path = Repo::normalized(path).to_string_lossy().replace('\\', "/");
+    Lint {
+        path: if path.is_empty() {
+            ".".to_string()
+        } else {
+            path
+        },
+        position: None,
+        message,
+    }
+}
+
+/// Lints the kraglefile behind a path, a name or an URI
+///
+/// Local dependencies (`./` uris) of a local kraglefile are resolved against its folder.
+pub fn lint_kraglefile(uri: &String) -> io::Result<Vec<Lint>> {
+    let source = Repo::fetch(uri)?;
+    let location = Repo::whereis(uri)?;
+    let folder = if location == STDIO || Repo::is_remote(&location) {
+        Path::new("")
+    } else {
+        Path::new(&location).parent().unwrap_or(Path::new(""))
+    };
+    // Fields are checked with their position when the source can be read as YAML, the other
+    // formats report them through their parse error
+    if source.format.is_yaml_compatible() {
+        let located = Located::parse(&String::from_utf8_lossy(&source.data))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let lints = lint_fields(&located);
+        if !lints.is_empty() {
+            return Ok(lints);
+        }
+    }
+    match Repo::parse_migrated(&source) {
+        Ok((repo, deprecations)) => {
+            let mut lints: Vec<Lint> = deprecations
+                .into_iter()
+                .map(|deprecation| {
+                    lint(
+                        Path::new(&deprecation.path),
+                        format!(
+                            "deprecated field `{}` {}, run `kragle migrate`",
+                            deprecation.field, deprecation.message
+                        ),
+                    )
+                })
+                .collect();
+            lints.extend(lint_repo(&repo, Some(folder)));
+            Ok(lints)
+        }
+        Err(e) => Ok(vec![lint(Path::new(""), e.to_string())]),
+    }
+}
+
+/// Checks a raw kraglefile for nodes with a missing or unknown type and unknown fields
+pub fn lint_fields(located: &Located) -> Vec<Lint> {
+    check_fields(located)
+        .into_iter()
+        .map(|error| Lint {
+            position: Some(error.position),
+            ..lint(&error.path, error.message)
+        })
+        .collect()
+}
+
+/// Checks a structure for authoring mistakes: invalid or duplicate names, content that does not
+/// decode or does not match its declared size and md5, invalid patterns and regexes, and (with
+/// `resolve`, the folder local dependencies are relative to) dependencies that cannot be loaded
+pub fn lint_repo(repo: &Repo, resolve: Option<&Path>) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    lint_node(repo, Path::new(""), true, resolve, &mut lints);
+    lints
+}
+
+fn lint_node(
+    node: &Repo,
+    parent: &Path,
+    is_root: bool,
+    resolve: Option<&Path>,
+    lints: &mut Vec<Lint>,
+) {
+    let Some(name) = node.name() else {
+        return;
+    };
+    let path = parent.join(name);
+
+    if name.is_empty() {
+        lints.push(lint(&path, "empty name".to_string()));
+    } else if name.contains('/') || name.contains('\\') {
+        lints.push(lint(
+            &path,
+            format!("name {:?} contains a path separator", name),
+        ));
+    } else if name == ".." || (name == "." && !is_root) {
+        lints.push(lint(&path, format!("name {:?} is not allowed", name)));
+    }
+
+    match node {
+        Repo::Directory {
+            kragle_version,
+            children,
+            dependencies,
+            ..
+        } => {
+            if kragle_version.is_some() && !is_root {
+                lints.push(lint(
+                    &path,
+                    "kragle_version is only allowed on the root node".to_string(),
+                ));
+            }
+
+            let mut names = BTreeSet::new();
+            for child in children {
+                if let Some(child_name) = child.name()
+                    && !names.insert(child_name)
+                {
+                    lints.push(lint(&path, format!("duplicate name {:?}", child_name)));
+                }
+            }
+
+            if let Some(folder) = resolve {
+                for dependency in dependencies.iter().flatten() {
+                    if let Err(e) = Repo::load(&local_uri(dependency, folder)) {
+                        lints.push(lint(
+                            &path,
+                            format!("dependency {:?} cannot be resolved: {}", dependency, e),
+                        ));
+                    }
+                }
+            }
+
+            for child in children {
+                lint_node(child, &path, false, resolve, lints);
+            }
+        }
+        Repo::File {
+            original_size,
+            original_md5,
+            assertions,
+            ..
+        } => {
+            match node.decoded() {
+                Ok(data) => {
+                    if let Some(original_size) = original_size
+                        && data.len() as u64 != *original_size
+                    {
+                        lints.push(lint(
+                            &path,
+                            format!(
+                                "original_size is {} but the content has {} bytes",
+                                original_size,
+                                data.len()
+                            ),
+                        ));
+                    }
+                    let computed_md5 = format!("{:x}", md5::compute(&data));
+                    if let Some(original_md5) = original_md5
+                        && computed_md5 != *original_md5
+                    {
+                        lints.push(lint(
+                            &path,
+                            format!(
+                                "original_md5 is {} but the content has md5 {}",
+                                original_md5, computed_md5
+                            ),
+                        ));
+                    }
+                }
+                Err(e) => lints.push(lint(&path, format!("content does not decode: {}", e))),
+            }
+            lint_assertions(assertions, &path, lints);
+        }
+        Repo::Pattern {
+            name,
+            min,
+            max,
+            assertions,
+            ..
+        } => {
+            if let Err(e) = glob::Pattern::new(name) {
+                lints.push(lint(&path, format!("invalid pattern: {}", e)));
+            }
+            if let (Some(min), Some(max)) = (min, max)
+                && min > max
+            {
+                lints.push(lint(
+                    &path,
+                    format!("min ({}) is greater than max ({})", min, max),
+                ));
+            }
+            lint_assertions(assertions, &path, lints);
+        }
+        Repo::None {} => {}
+    }
+}
+
+fn lint_assertions(assertions: &Option<Vec<Assertion>>, path: &Path, lints: &mut Vec<Lint>) {
+    for assertion in assertions.iter().flatten() {
+        if let Assertion::Matches(pattern) = assertion
+            && let Err(e) = Regex::new(pattern)
+        {
+            lints.push(lint(path, format!("invalid regex: {}", e)));
+        }
+    }
+}
+
+/// Resolves a local uri (`./` or `.\\`) against a folder, other uris are returned unchanged
+fn local_uri(uri: &String, folder: &Path) -> String {
+    match uri.strip_prefix("./").or_else(|| uri.strip_prefix(".\\")) {
+        Some(relative) if folder != Path::new("") => {
+            let path = folder.join(relative);
+            if path.is_absolute() || path.starts_with(".") {
+                path.display().to_string()
+            } else {
+                format!("./{}", path.display())
+            }
+        }
+        _ => uri.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KRAGLEFILE: &str = r##"
+type: directory
+name: "."
+dependencies:
+  - ./does-not-exist.yaml
+children:
+  - type: file
+    name: README.md
+    content: "# kragle\n"
+    original_size: 8
+    original_md5: f6ed5bb310a2f396778aa429eaca1113
+  - type: file
+    name: README.md
+    content: "not base85 é"
+    is_compressed: true
+  - type: file
+    name: src/lib.rs
+    content: ""
+    executable: true
+  - type: pattern
+    name: "*.rs"
+    min: 2
+    max: 1
+    assertions:
+      - matches: "("
+"##;
+
+    #[test]
+    fn test_lint_fields() {
+        let lints = lint_fields(&Located::parse(KRAGLEFILE).unwrap());
+        assert_eq!(lints.len(), 1);
+        assert_eq!(lints[0].path, "src/lib.rs");
+        assert_eq!(
+            lints[0].position,
+            Some(Position {
+                line: 19,
+                column: 5
+            })
+        );
+        assert!(
+            lints[0]
+                .message
+                .starts_with("unknown field `executable` in file node")
+        );
+
+        let lints = lint_fields(&Located::parse("type: symlink\nname: link\n").unwrap());
+        assert!(
+            lints[0]
+                .to_string()
+                .starts_with("link: unknown node type `symlink`")
+        );
+        assert!(lints[0].to_string().ends_with("(line 1, column 7)"));
+    }
+
+    #[test]
+    fn test_lint_repo() {
+        let repo: Repo =
+            serde_yml::from_str(&KRAGLEFILE.replace("    executable: true\n", "")).unwrap();
+
+        let messages: Vec<String> = lint_repo(&repo, Some(Path::new("")))
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(messages.len(), 7);
+        assert_eq!(messages[0], ".: duplicate name \"README.md\"");
+        assert!(
+            messages[1].starts_with(".: dependency \"./does-not-exist.yaml\" cannot be resolved")
+        );
+        assert_eq!(
+            messages[2],
+            "README.md: original_size is 8 but the content has 9 bytes"
+        );
+        assert!(messages[3].starts_with("README.md: content does not decode"));
+        assert_eq!(
+            messages[4],
+            "src/lib.rs: name \"src/lib.rs\" contains a path separator"
+        );
+        assert_eq!(messages[5], "*.rs: min (2) is greater than max (1)");
+        assert!(messages[6].starts_with("*.rs: invalid regex"));
+    }
+
+    #[test]
+    fn test_lint_kraglefile_dependencies() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        std::fs::write(
+            temp_dir.path().join("base.yaml"),
+            "type: directory\nname: \".\"\nchildren: []\n",
+        )
+        .unwrap();
+        let kraglefile = temp_dir.path().join("app.yaml");
+        std::fs::write(
+            &kraglefile,
+            "type: directory\nname: \".\"\ndependencies:\n  - ./base.yaml\nchildren: []\n",
+        )
+        .unwrap();
+
+        // The dependency is found next to the kraglefile, whatever the working directory
+        let lints = lint_kraglefile(&kraglefile.display().to_string()).unwrap();
+        assert!(lints.is_empty(), "{:?}", lints);
+        assert_eq!(
+            local_uri(&"./base.yaml".to_string(), Path::new("./kraglefile")),
+            "./kraglefile/base.yaml"
+        );
+        assert_eq!(
+            local_uri(&"./base.yaml".to_string(), Path::new("kraglefile")),
+            "./kraglefile/base.yaml"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::Lazy;
+use yaml_rust2::parser::{Event, Parser};
+use yaml_rust2::scanner::Marker;
+
+use crate::repo::Repo;
+
+/// Fields accepted by each node type, taken from the JSON Schema generated from `Repo`
+static NODE_FIELDS: Lazy<Vec<(String, Vec<String>)>> = Lazy::new(|| {
+    let schema = serde_json::to_value(schemars::schema_for!(Repo)).unwrap_or_default();
+    let variants = schema["oneOf"].as_array().cloned().unwrap_or_default();
+    variants
+        .iter()
+        .filter_map(|variant| {
+            let node_type = variant["properties"]["type"]["const"].as_str()?;
+            let mut fields: Vec<String> =
+                variant["properties"].as_object()?.keys().cloned().collect();
+            // `type` comes first, the other fields in schema order
+            fields.sort_by_key(|field| *field != "type");
+            Some((node_type.to_string(), fields))
+        })
+        .collect()
+});
+
+/// Fields accepted by a node type, `None` for unknown types
+pub fn node_fields(node_type: &str) -> Option<&'static [String]> {
+    NODE_FIELDS
+        .iter()
+        .find(|(name, _)| name == node_type)
+        .map(|(_, fields)| fields.as_slice())
+}
+
+/// Position in the source of a kraglefile, starting at line 1, column 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl From<Marker> for Position {
+    fn from(marker: Marker) -> Self {
+        Position {
+            line: marker.line(),
+            column: marker.col() + 1,
+        }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+/// YAML (or JSON) document keeping the position of its keys and values
+#[derive(Debug)]
+pub enum Located {
+    Mapping(Position, Vec<(String, Position, Located)>),
+    Sequence(Position, Vec<Located>),
+    Scalar(Position, String),
+}
+
+impl Located {
+    /// Parses the first document of a YAML or JSON source
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut parser = Parser::new_from_str(source);
+        loop {
+            let (event, marker) = parser.next_token().map_err(|e| e.to_string())?;
+            match event {
+                Event::StreamEnd => return Err("empty document".to_string()),
+                Event::StreamStart | Event::DocumentStart => {}
+                event => return Self::parse_node(&mut parser, event, marker),
+            }
+        }
+    }
+
+    fn parse_node(
+        parser: &mut Parser<std::str::Chars<'_>>,
+        event: Event,
+        marker: Marker,
+    ) -> Result<Self, String> {
+        let position = Position::from(marker);
+        match event {
+            Event::Scalar(value, ..) => Ok(Located::Scalar(position, value)),
+            Event::Alias(_) => Ok(Located::Scalar(position, String::new())),
+            Event::SequenceStart(..) => {
+                let mut items = Vec::new();
+                loop {
+                    let (event, marker) = parser.next_token().map_err(|e| e.to_string())?;
+                    if event == Event::SequenceEnd {
+                        return Ok(Located::Sequence(position, items));
+                    }
+                    items.push(Self::parse_node(parser, event, marker)?);
+                }
+            }
+            Event::MappingStart(..) => {
+                let mut entries = Vec::new();
+                loop {
+                    let (event, marker) = parser.next_token().map_err(|e| e.to_string())?;
+                    let key = match event {
+                        Event::MappingEnd => return Ok(Located::Mapping(position, entries)),
+                        Event::Scalar(key, ..) => key,
+                        event => {
+                            // Complex keys are kept but never looked up
+                            Self::parse_node(parser, event, marker)?;
+                            String::new()
+                        }
+                    };
+                    let (event, value_marker) = parser.next_token().map_err(|e| e.to_string())?;
+                    let value = Self::parse_node(parser, event, value_marker)?;
+                    entries.push((key, Position::from(marker), value));
+                }
+            }
+            event => Err(format!("unexpected {:?} at {}", event, position)),
+        }
+    }
+
+    pub fn position(&self) -> Position {
+        match self {
+            Located::Mapping(position, _)
+            | Located::Sequence(position, _)
+            | Located::Scalar(position, _) => *position,
+        }
+    }
+
+    /// Value of a key of a mapping
+    pub fn get(&self, key: &str) -> Option<&Located> {
+        match self {
+            Located::Mapping(_, entries) => entries
+                .iter()
+                .find(|(name, ..)| name == key)
+                .map(|(.., value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Located::Scalar(_, value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Structural mistake of a kraglefile, with its position in the source
+#[derive(Debug, PartialEq, Eq)]
+pub struct FieldError {
+    /// Path of the node in the structure
+    pub path: PathBuf,
+    pub position: Position,
+    pub message: String,
+}
+
+/// Looks for nodes that are not maps, have a missing or unknown type, or have unknown fields, in
+/// the order of the source
+pub fn check_fields(root: &Located) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+    check_node(root, Path::new(""), &mut errors);
+    errors
+}
+
+fn check_node(node: &Located, parent: &Path, errors: &mut Vec<FieldError>) {
+    let Located::Mapping(position, entries) = node else {
+        errors.push(FieldError {
+            path: parent.to_path_buf(),
+            position: node.position(),
+            message: "node is not a map".to_string(),
+        });
+        return;
+    };
+    let path = parent.join(node.get("name").and_then(Located::as_str).unwrap_or(""));
+
+    let node_type = node.get("type").and_then(Located::as_str);
+    let Some(node_type) = node_type else {
+        errors.push(FieldError {
+            path,
+            position: *position,
+            message: "missing field `type`".to_string(),
+        });
+        return;
+    };
+    let Some(fields) = node_fields(node_type) else {
+        let position = node.get("type").map_or(*position, Located::position);
+        errors.push(FieldError {
+            path,
+            position,
+            message: format!(
+                "unknown node type `{}`, expected one of {}",
+                node_type,
+                NODE_FIELDS
+                    .iter()
+                    .map(|(name, _)| format!("`{}`", name))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        });
+        return;
+    };
+
+    for (key, key_position, value) in entries {
+        if !fields.contains(key) {
+            errors.push(FieldError {
+                path: path.clone(),
+                position: *key_position,
+                message: format!(
+                    "unknown field `{}` in {} node, expected one of {}",
+                    key,
+                    node_type,
+                    fields
+                        .iter()
+                        .map(|field| format!("`{}`", field))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            });
+        } else if key == "children"
+            && let Located::Sequence(_, children) = value
+        {
+            for child in children {
+                check_node(child, &path, errors);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let located =
+            Located::parse("type: file\nname: a\nassertions:\n  - contains: b\n").unwrap();
+        assert_eq!(
+            located.get("name").unwrap().position(),
+            Position { line: 2, column: 7 }
+        );
+        let Some(Located::Sequence(position, items)) = located.get("assertions") else {
+            panic!("assertions is not a sequence");
+        };
+        assert_eq!(*position, Position { line: 4, column: 3 });
+        assert_eq!(items[0].get("contains").unwrap().as_str(), Some("b"));
+
+        let located = Located::parse("{\"type\": \"none\",\n \"name\": \"c\"}").unwrap();
+        assert_eq!(located.get("name").unwrap().as_str(), Some("c"));
+    }
+
+    #[test]
+    fn test_check_fields() {
+        let located = Located::parse(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: a
+    contnt: x
+  - type: symlink
+    name: b
+  - name: c
+"#,
+        )
+        .unwrap();
+
+        let errors = check_fields(&located);
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0].path, Path::new("./a"));
+        assert_eq!(errors[0].position, Position { line: 7, column: 5 });
+        assert!(
+            errors[0]
+                .message
+                .starts_with("unknown field `contnt` in file node, expected one of `type`")
+        );
+        assert_eq!(
+            errors[1].position,
+            Position {
+                line: 8,
+                column: 11
+            }
+        );
+        assert!(errors[1].message.starts_with("unknown node type `symlink`"));
+        assert_eq!(errors[2].message, "missing field `type`");
+    }
+
+    #[test]
+    fn test_node_fields() {
+        let fields = node_fields("file").unwrap();
+        assert_eq!(fields[0], "type");
+        assert!(fields.iter().any(|field| field == "codec"));
+        assert_eq!(node_fields("none").unwrap(), ["type"]);
+        assert!(node_fields("symlink").is_none());
+    }
+}
@@ -1,12 +1,27 @@
 use clap::Parser;
 use kragle::cache::cache_path;
+use kragle::codec::{Codec, Encoding, EncodingChoice, Storage};
+use kragle::convert::set_storage;
 use kragle::dialog::{self, Dialog};
+use kragle::diff::{diff_folder, diff_repos, render_repo_diff};
+use kragle::edit::{self, Edit};
+use kragle::format::Format;
 use kragle::globals::MANIFEST;
+use kragle::grep::grep;
+use kragle::lint::lint_kraglefile;
 use kragle::manifest::{load_manifest, print_manifest};
-use kragle::repo::Repo;
-use std::fs::{self, File};
+use kragle::pack::Pack;
+use kragle::refresh::refresh;
+use kragle::repo::{Repo, STDIO};
+use kragle::stats::Stats;
+use kragle::tree::{TreeNode, TreeOptions};
+use kragle::validation::{self, Severity};
+use kragle::version::{self, CURRENT_VERSION};
+use kragle::watch::Watch;
+use regex::RegexBuilder;
+use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Export/import a folder structure as JSON (with optional compression)
 #[derive(Parser, Debug)]
@@ -16,37 +31,238 @@ struct Cli {
     command: Commands,
 }
 
+/// How file contents are stored when exporting
+#[derive(clap::Args, Debug)]
+struct StorageArgs {
+    /// Compress file contents (xz unless `--codec` is given)
+    #[arg(short, long)]
+    compressed: bool,
+    /// Compress file contents with this codec
+    #[arg(long, value_enum)]
+    codec: Option<Codec>,
+    /// Compression level (codec default when omitted)
+    #[arg(long)]
+    level: Option<u32>,
+    /// Encoding of compressed contents (default base85), `auto` choosing it for each file:
+    /// readable text, compressed only when it saves space, base85 for binaries
+    #[arg(long, value_enum)]
+    encoding: Option<EncodingChoice>,
+}
+
+impl StorageArgs {
+    fn storage(&self) -> io::Result<Storage> {
+        let encoding = match self.encoding {
+            Some(EncodingChoice::Auto) => return Storage::auto(self.codec, self.level),
+            Some(EncodingChoice::Base85) => Some(Encoding::Base85),
+            Some(EncodingChoice::Base64) => Some(Encoding::Base64),
+            Some(EncodingChoice::Utf8) => Some(Encoding::Utf8),
+            None => None,
+        };
+        let codec = match self.codec {
+            Some(codec) => codec,
+            None if self.compressed => Codec::Xz,
+            None => Codec::None,
+        };
+        Storage::new(codec, self.level, encoding)
+    }
+}
+
 #[derive(clap::Subcommand, Debug)]
 enum Commands {
     /// Export a folder to a JSON file
     Export {
         /// Path to the folder to export
         folder: String,
-        /// Output JSON file
+        /// Output JSON file, `-` for stdout (YAML)
         output: String,
-        /// Compress file contents (a85/xz)
-        #[arg(short, long)]
-        compressed: bool,
+        #[command(flatten)]
+        storage: StorageArgs,
+    },
+    /// Update a kraglefile from its source folder, keeping its hand-written metadata
+    Refresh {
+        /// Kraglefile to update
+        input: String,
+        /// Folder the kraglefile was exported from
+        folder: String,
+        /// How the contents of new files are stored
+        #[command(flatten)]
+        storage: StorageArgs,
     },
     /// Import a folder structure from a JSON file
     Import {
-        /// Input JSON file
+        /// Input JSON file, `-` for stdin
         input: String,
         /// Target folder to recreate
         target_folder: String,
     },
     /// List contents of directories in a tree-like format
     Tree {
+        /// Input JSON file, `-` for stdin
+        input: String,
+        /// Only show the nodes matching this path or glob (and their parents)
+        filter: Option<String>,
+        /// Descend at most this many levels below the root
+        #[arg(short, long)]
+        depth: Option<usize>,
+        /// Print the tree as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+    /// Print the decoded content of a file of a kraglefile
+    Cat {
+        /// Input JSON file
+        input: String,
+        /// Path of the file in the kraglefile
+        path: String,
+    },
+    /// Recreate a single file or directory of a kraglefile
+    Extract {
+        /// Input JSON file
+        input: String,
+        /// Path of the file or directory in the kraglefile
+        path: String,
+        /// Folder to write it into
+        dest: String,
+    },
+    /// Search the decoded contents of kraglefiles for a regex, printed as `path:line:match`
+    Grep {
+        /// Regex to search for
+        pattern: String,
+        /// Input JSON files
+        #[arg(required_unless_present = "manifest")]
+        inputs: Vec<String>,
+        /// Search every kraglefile listed in the manifest
+        #[arg(short, long)]
+        manifest: bool,
+        /// Search case-insensitively
+        #[arg(short, long)]
+        ignore_case: bool,
+    },
+    /// Show the composition of a kraglefile: counts, sizes and compression ratios
+    Stats {
         /// Input JSON file
         input: String,
+        /// Number of largest files listed
+        #[arg(short = 'n', long, default_value_t = 10)]
+        top: usize,
+        /// Print the statistics of every file as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+    /// Add a local file or folder to a kraglefile
+    Add {
+        /// Kraglefile to modify
+        input: String,
+        /// Local file or folder to add
+        local: String,
+        /// Path of the new node in the kraglefile
+        #[arg(long)]
+        at: String,
+        #[command(flatten)]
+        storage: StorageArgs,
+    },
+    /// Remove a file or directory from a kraglefile
+    Rm {
+        /// Kraglefile to modify
+        input: String,
+        /// Path of the node in the kraglefile
+        path: String,
+    },
+    /// Move or rename a file or directory of a kraglefile
+    Mv {
+        /// Kraglefile to modify
+        input: String,
+        /// Current path of the node in the kraglefile
+        from: String,
+        /// New path of the node in the kraglefile
+        to: String,
+    },
+    /// Edit the content of a file of a kraglefile in $EDITOR
+    Edit {
+        /// Kraglefile to modify
+        input: String,
+        /// Path of the file in the kraglefile
+        path: String,
     },
     /// Validated the structure of a directory from a JSON
     Validate {
-        /// Input JSON file
+        /// Input JSON file, `-` for stdin
         input: String,
         /// Target folder to validate against
         target_folder: String,
+        /// Restore missing files and overwrite mismatched ones
+        #[arg(long)]
+        fix: bool,
+        /// Overwrite mismatched files without asking for confirmation
+        #[arg(short, long, requires = "fix")]
+        yes: bool,
+        /// Keep running and re-validate the folder when its files change
+        #[arg(short, long, conflicts_with = "fix")]
+        watch: bool,
+        /// Exit with an error status when a check of this severity (or above) fails
+        #[arg(long, value_enum, default_value_t = Severity::Error)]
+        fail_on: Severity,
+    },
+    /// Show the differences between a kraglefile and a folder as a unified diff
+    Diff {
+        /// Input JSON file
+        input: String,
+        /// Folder to compare with
+        folder: String,
+        /// Emit a patch accepted by `git apply`
+        #[arg(short, long)]
+        patch: bool,
     },
+    /// Compare two kraglefiles node by node
+    DiffRepo {
+        /// Old kraglefile
+        old: String,
+        /// New kraglefile
+        new: String,
+        /// Print the differences as JSON
+        #[arg(short, long)]
+        json: bool,
+    },
+    /// Check a kraglefile for authoring mistakes
+    Lint {
+        /// Input JSON file
+        input: String,
+    },
+    /// Rewrite a kraglefile to the current version of the format
+    Migrate {
+        /// Input JSON file
+        input: String,
+        /// Write the migrated kraglefile here instead of overwriting the input
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Convert a kraglefile to another format (by extension) or compress/decompress its files
+    Convert {
+        /// Input JSON file
+        input: String,
+        /// Output file, its extension choosing the format
+        output: String,
+        /// Compress the contents of the files (xz unless `--codec` is given)
+        #[arg(long, conflicts_with = "decompress")]
+        compress: bool,
+        /// Store the contents of text files as plain text
+        #[arg(long, conflicts_with_all = ["codec", "level", "encoding"])]
+        decompress: bool,
+        /// Compress the contents of the files with this codec
+        #[arg(long, value_enum)]
+        codec: Option<Codec>,
+        /// Compression level (codec default when omitted)
+        #[arg(long)]
+        level: Option<u32>,
+        /// Encoding of compressed contents (default base85)
+        #[arg(long, value_enum)]
+        encoding: Option<Encoding>,
+        /// Only (de)compress the files whose path matches this glob
+        #[arg(long)]
+        only: Option<String>,
+    },
+    /// Print the JSON Schema of the kraglefile format
+    Schema,
     /// list kraglefile based on the manifest
     List,
     /// Purge cache
@@ -57,33 +273,78 @@ enum Commands {
     },
 }
 
-fn main() -> io::Result<()> {
+fn main() {
     let cli = Cli::parse();
+    if let Err(e) = run(&cli) {
+        let _ = writeln!(io::stderr(), "Error: {}", e);
+        std::process::exit(1);
+    }
+}
 
+/// Opens a local `.kpack` without reading the contents of its files, `None` for other kraglefiles
+fn open_pack(input: &String) -> io::Result<Option<Pack<fs::File>>> {
+    let resolve_uri = Repo::whereis(input)?;
+    if Repo::is_remote(&resolve_uri) || Format::from_extension(&resolve_uri) != Some(Format::Pack) {
+        return Ok(None);
+    }
+    Pack::open(resolve_uri).map(Some)
+}
+
+fn run(cli: &Cli) -> io::Result<()> {
     match &cli.command {
         Commands::Export {
             folder,
             output,
-            compressed,
+            storage,
         } => {
-            let repo = Repo::from_folder(folder, *compressed, 0)?;
-            let mut file = File::create(output)?;
-            if output.ends_with(".json") {
-                serde_json::to_writer_pretty(&mut file, &repo)?;
-            } else if output.ends_with(".yaml") || output.ends_with(".yml") {
-                serde_yml::to_writer(file, &repo).unwrap();
-            } else {
-                panic!("Unsupported file format");
+            let storage = storage.storage()?;
+            let repo = Repo::from_folder(folder, storage, 0)?;
+            repo.save(output)?;
+            if output == STDIO {
+                return Ok(());
             }
 
             writeln!(
                 io::stdout(),
-                "Exported folder \"{}\" to \"{}\" (compressed: {})",
+                "Exported folder \"{}\" to \"{}\" (codec: {})",
                 folder,
                 output,
-                compressed
+                storage.codec
             )?;
         }
+        Commands::Refresh {
+            input,
+            folder,
+            storage,
+        } => {
+            let storage = storage.storage()?;
+            let source = Repo::fetch(input)?;
+            if source.uri == STDIO {
+                return Err(io::Error::other(
+                    "Cannot modify a kraglefile read from stdin",
+                ));
+            }
+            if Repo::is_remote(&source.uri) {
+                return Err(io::Error::other(format!(
+                    "Cannot modify remote kraglefile {}",
+                    source.uri
+                )));
+            }
+            let old = Repo::parse(&source)?;
+            let mut repo = Repo::parse(&source)?;
+            refresh(&mut repo, Path::new(folder), storage)?;
+
+            let diffs = diff_repos(&old, &repo)?;
+            write!(io::stdout(), "{}", render_repo_diff(&diffs))?;
+            edit::save_refreshed(source, &old, &repo)?;
+            Dialog::new(format!(
+                "Refreshed \"{}\" from \"{}\": {} nodes changed",
+                input,
+                folder,
+                diffs.len()
+            ))
+            .end_print(dialog::Status::Ok);
+        }
         Commands::Import {
             input,
             target_folder,
@@ -109,16 +370,353 @@ fn main() -> io::Result<()> {
             ));
             dialog.end_print(dialog::Status::Ok);
         }
-        Commands::Tree { input } => {
-            let repo = Repo::new(input)?;
-            repo.display_tree("", true)?
+        Commands::Tree {
+            input,
+            filter,
+            depth,
+            json,
+        } => {
+            let options = TreeOptions {
+                depth: *depth,
+                filter: filter.clone(),
+            };
+            // Packs are shown from their index, without reading the contents
+            let pack = open_pack(input)?;
+            if *json {
+                let tree = match &pack {
+                    Some(pack) => TreeNode::new(pack.structure(), &options)?,
+                    None => TreeNode::new(&Repo::load(input)?, &options)?,
+                };
+                serde_json::to_writer_pretty(io::stdout(), &tree)?;
+                writeln!(io::stdout())?;
+            } else {
+                let tree = match &pack {
+                    Some(pack) => TreeNode::new(pack.structure(), &options)?,
+                    None => TreeNode::new(&Repo::new(input)?, &options)?,
+                };
+                if let Some(tree) = tree {
+                    write!(io::stdout(), "{}", tree.render())?;
+                }
+            }
+        }
+        Commands::Cat { input, path } => {
+            let data = match open_pack(input)? {
+                Some(mut pack) => {
+                    if !matches!(pack.structure().find(path)?, Repo::File { .. }) {
+                        return Err(io::Error::other(format!("{} is not a file", path)));
+                    }
+                    pack.read(path)?
+                }
+                None => {
+                    let repo = Repo::load(input)?;
+                    let node = repo.find(path)?;
+                    if !matches!(node, Repo::File { .. }) {
+                        return Err(io::Error::other(format!("{} is not a file", path)));
+                    }
+                    node.decoded()?
+                }
+            };
+            io::stdout().write_all(&data)?;
+        }
+        Commands::Extract { input, path, dest } => {
+            let repo;
+            let node = match open_pack(input)? {
+                Some(mut pack) => {
+                    repo = pack.load(path)?;
+                    &repo
+                }
+                None => {
+                    repo = Repo::new(input)?;
+                    repo.find(path)?
+                }
+            };
+            fs::create_dir_all(dest)?;
+
+            let mut dialog = Dialog::new(format!("Extracting {}", path));
+            node.to_folder(dest, &mut dialog)?;
+            dialog.set_msg(format!(
+                "Extracted \"{}\" from \"{}\" into \"{}\"",
+                path, input, dest
+            ));
+            dialog.end_print(dialog::Status::Ok);
+        }
+        Commands::Grep {
+            pattern,
+            inputs,
+            manifest,
+            ignore_case,
+        } => {
+            let regex = RegexBuilder::new(pattern)
+                .case_insensitive(*ignore_case)
+                .build()
+                .map_err(io::Error::other)?;
+            let mut inputs = inputs.clone();
+            if *manifest {
+                let manifest = load_manifest(&MANIFEST).map_err(io::Error::other)?;
+                // Kraglefiles of a local manifest are next to it
+                let folder = Path::new(MANIFEST.as_str())
+                    .parent()
+                    .unwrap_or(Path::new(""));
+                inputs.extend(manifest.into_iter().map(|entry| {
+                    if Repo::is_remote(&MANIFEST) {
+                        entry.name
+                    } else {
+                        Path::new(".")
+                            .join(folder)
+                            .join(entry.name)
+                            .to_string_lossy()
+                            .to_string()
+                    }
+                }));
+            }
+
+            let mut found = false;
+            for input in &inputs {
+                let repo = match Repo::load(input) {
+                    Ok(repo) => repo,
+                    Err(e) => {
+                        writeln!(io::stderr(), "{}: {}", input, e)?;
+                        continue;
+                    }
+                };
+                // Paths are prefixed with their kraglefile when searching several of them
+                let prefix = if inputs.len() > 1 {
+                    format!("{}:", input)
+                } else {
+                    String::new()
+                };
+                for found_match in grep(&repo, &regex)? {
+                    writeln!(io::stdout(), "{}", found_match.colorized(&prefix))?;
+                    found = true;
+                }
+            }
+            if !found {
+                std::process::exit(1);
+            }
+        }
+        Commands::Stats { input, top, json } => {
+            let stats = Stats::new(&Repo::load(input)?)?;
+            if *json {
+                serde_json::to_writer_pretty(io::stdout(), &stats)?;
+                writeln!(io::stdout())?;
+            } else {
+                write!(io::stdout(), "{}", stats.render(*top))?;
+            }
+        }
+        Commands::Add {
+            input,
+            local,
+            at,
+            storage,
+        } => {
+            let storage = storage.storage()?;
+            let node = if Path::new(local).is_dir() {
+                Repo::from_folder(local, storage, 1)?
+            } else {
+                Repo::from_file(local, storage)?
+            };
+            edit::apply(
+                input,
+                Edit::Add {
+                    at: PathBuf::from(at),
+                    node,
+                },
+            )?;
+            Dialog::new(format!("Added \"{}\" to \"{}\" at {}", local, input, at))
+                .end_print(dialog::Status::Ok);
+        }
+        Commands::Rm { input, path } => {
+            edit::apply(
+                input,
+                Edit::Remove {
+                    at: PathBuf::from(path),
+                },
+            )?;
+            Dialog::new(format!("Removed {} from \"{}\"", path, input))
+                .end_print(dialog::Status::Ok);
+        }
+        Commands::Mv { input, from, to } => {
+            edit::apply(
+                input,
+                Edit::Move {
+                    from: PathBuf::from(from),
+                    to: PathBuf::from(to),
+                },
+            )?;
+            Dialog::new(format!("Moved {} to {} in \"{}\"", from, to, input))
+                .end_print(dialog::Status::Ok);
+        }
+        Commands::Edit { input, path } => {
+            let repo = Repo::load(input)?;
+            let node = repo.find(path)?;
+            if !matches!(node, Repo::File { .. }) {
+                return Err(io::Error::other(format!("{} is not a file", path)));
+            }
+            let data = node.decoded()?;
+            let edited = edit::open_in_editor(&data, node.name().unwrap_or("content"))?;
+            if edited == data {
+                Dialog::new(format!("{} unchanged", path)).end_print(dialog::Status::Ok);
+                return Ok(());
+            }
+            edit::apply(
+                input,
+                Edit::SetContent {
+                    at: PathBuf::from(path),
+                    data: edited,
+                },
+            )?;
+            Dialog::new(format!("Updated {} in \"{}\"", path, input)).end_print(dialog::Status::Ok);
         }
         Commands::Validate {
             input,
             target_folder,
+            fix,
+            yes,
+            watch,
+            fail_on,
         } => {
             let repo = Repo::new(input)?;
-            repo.validated(target_folder)?
+            if *watch {
+                Watch::new(&repo, Path::new(target_folder))?
+                    .run()
+                    .map_err(io::Error::other)?;
+                return Ok(());
+            }
+            let issues = repo.validated(target_folder)?;
+            for issue in &issues {
+                writeln!(io::stdout(), "{}", issue)?;
+            }
+            validation::print_summary(&issues);
+            let failed = if *fix {
+                validation::fix(&issues, *yes)?;
+                validation::failed(&repo.validated(target_folder)?, *fail_on)
+            } else {
+                validation::failed(&issues, *fail_on)
+            };
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        Commands::Diff {
+            input,
+            folder,
+            patch,
+        } => {
+            let repo = Repo::load(input)?;
+            write!(
+                io::stdout(),
+                "{}",
+                diff_folder(&repo, Path::new(folder), *patch)?
+            )?;
+        }
+        Commands::DiffRepo { old, new, json } => {
+            let diffs = diff_repos(&Repo::load(old)?, &Repo::load(new)?)?;
+            if *json {
+                serde_json::to_writer_pretty(io::stdout(), &diffs)?;
+                writeln!(io::stdout())?;
+            } else {
+                write!(io::stdout(), "{}", render_repo_diff(&diffs))?;
+            }
+        }
+        Commands::Lint { input } => {
+            let lints = lint_kraglefile(input)?;
+            for lint in &lints {
+                writeln!(io::stdout(), "{}", lint)?;
+            }
+            if lints.is_empty() {
+                Dialog::new(format!("Lint {}: no problems found", input))
+                    .end_print(dialog::Status::Ok);
+            } else {
+                Dialog::new(format!("Lint {}: {} problems found", input, lints.len()))
+                    .end_print(dialog::Status::Failed);
+                std::process::exit(1);
+            }
+        }
+        Commands::Migrate { input, output } => {
+            let source = Repo::fetch(input)?;
+            let from = version::version(&Repo::parse_value(&source)?)?;
+            let (repo, deprecations) = Repo::parse_migrated(&source)?;
+            for deprecation in &deprecations {
+                writeln!(io::stderr(), "{}", deprecation)?;
+            }
+
+            let output = match output {
+                Some(output) => {
+                    repo.save(output)?;
+                    output
+                }
+                None if Repo::is_remote(&source.uri) => {
+                    return Err(io::Error::other(format!(
+                        "Cannot overwrite remote kraglefile {}, use --output",
+                        source.uri
+                    )));
+                }
+                None => {
+                    repo.save_as(&source.uri, source.format)?;
+                    &source.uri
+                }
+            };
+            if output == STDIO {
+                return Ok(());
+            }
+            Dialog::new(format!(
+                "Migrated \"{}\" from version {} to {} into \"{}\" ({} fields changed)",
+                input,
+                from,
+                CURRENT_VERSION,
+                output,
+                deprecations.len()
+            ))
+            .end_print(dialog::Status::Ok);
+        }
+        Commands::Convert {
+            input,
+            output,
+            compress,
+            decompress,
+            codec,
+            level,
+            encoding,
+            only,
+        } => {
+            let mut repo = Repo::load(input)?;
+            let compress = *compress || codec.is_some() || level.is_some() || encoding.is_some();
+            let mut recoded = None;
+            if compress || *decompress {
+                let storage = if compress {
+                    Storage::new(codec.unwrap_or(Codec::Xz), *level, *encoding)?
+                } else {
+                    Storage::default()
+                };
+                let changes = set_storage(&mut repo, storage, only.as_deref())?;
+                for path in &changes.skipped {
+                    writeln!(io::stderr(), "{}: binary content left compressed", path)?;
+                }
+                recoded = Some(changes.changed.len());
+            }
+            repo.save(output)?;
+            if output == STDIO {
+                return Ok(());
+            }
+
+            if let Some(count) = recoded {
+                writeln!(
+                    io::stdout(),
+                    "{} {} files",
+                    if compress {
+                        "Compressed"
+                    } else {
+                        "Decompressed"
+                    },
+                    count
+                )?;
+            }
+            Dialog::new(format!("Converted \"{}\" to \"{}\"", input, output))
+                .end_print(dialog::Status::Ok);
+        }
+        Commands::Schema => {
+            serde_json::to_writer_pretty(io::stdout(), &schemars::schema_for!(Repo))?;
+            writeln!(io::stdout())?;
         }
         Commands::List => {
             let manifest = load_manifest(&MANIFEST);
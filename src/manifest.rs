@@ -1,37 +1,42 @@
 use anyhow::anyhow;
 use serde::Deserialize;
 use std::fs::File;
-use std::io::{self, Error, ErrorKind, Read, Write};
+use std::io::{self, Read, Write};
 
 use crate::cache::get_uri;
+use crate::format::Format;
 
 #[derive(Debug, Deserialize)]
 pub struct Manifest {
-    name: String,
-    description: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+}
+
+/// Manifest written in TOML, its entries being a `[[kraglefile]]` array
+#[derive(Debug, Deserialize)]
+struct TomlManifest {
+    #[serde(default)]
+    kraglefile: Vec<Manifest>,
 }
 
 pub fn load_manifest(uri: &str) -> anyhow::Result<Vec<Manifest>> {
-    if uri.starts_with("http://") || uri.starts_with("https://") {
-        let data = get_uri(uri).unwrap();
-        let manifests: Vec<Manifest> =
-            serde_yml::from_slice(&data).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-        Ok(manifests)
+    let is_remote = uri.starts_with("http://") || uri.starts_with("https://");
+    let format = match Format::from_extension(uri) {
+        Some(format) => format,
+        None if is_remote => Format::Yaml,
+        None => return Err(anyhow!("Unsupported manifest file type: {}", uri)),
+    };
+    let data = if is_remote {
+        get_uri(uri)?
     } else {
-        let mut file = File::open(uri)?;
-        let mut content = String::new();
-        file.read_to_string(&mut content)?;
-        if uri.ends_with(".json") {
-            let manifests: Vec<Manifest> = serde_json::from_str(&content)
-                .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-            Ok(manifests)
-        } else if uri.ends_with(".yaml") || uri.ends_with(".yml") {
-            let manifests: Vec<Manifest> =
-                serde_yml::from_str(&content).map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
-            Ok(manifests)
-        } else {
-            Err(anyhow!("URI must start with http:// or https://"))
-        }
+        let mut content = Vec::new();
+        File::open(uri)?.read_to_end(&mut content)?;
+        content
+    };
+
+    match format {
+        Format::Toml => Ok(format.parse::<TomlManifest>(&data)?.kraglefile),
+        _ => Ok(format.parse(&data)?),
     }
 }
 
@@ -44,3 +49,36 @@ pub fn print_manifest(manifest: &[Manifest]) -> io::Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_load_toml_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("manifest.toml");
+        fs::write(
+            &path,
+            r#"
+[[kraglefile]]
+name = "kragle-repo.yaml"
+
+[[kraglefile]]
+name = "kragle-rust-simpleA.toml"
+description = "Rust for simple project"
+"#,
+        )
+        .unwrap();
+
+        let manifest = load_manifest(&path.to_string_lossy()).unwrap();
+        assert_eq!(manifest.len(), 2);
+        assert_eq!(manifest[0].name, "kragle-repo.yaml");
+        assert_eq!(
+            manifest[1].description.as_deref(),
+            Some("Rust for simple project")
+        );
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use xz2::read::{XzDecoder, XzEncoder};
+
+use crate::diff::has_content;
+use crate::repo::Repo;
+use crate::version;
+
+/// First bytes of a `.kpack` file
+pub const MAGIC: &[u8; 8] = b"KRAGPACK";
+/// Version of the layout of `.kpack` files
+const PACK_VERSION: u32 = 1;
+
+/// Location of the content of a file in a pack
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Path of the file in the structure
+    pub path: String,
+    /// Offset of the compressed blob from the end of the index
+    pub offset: u64,
+    /// Size of the compressed blob
+    pub length: u64,
+    pub original_size: u64,
+    /// md5 of the decoded content, checked on every read
+    pub original_md5: String,
+}
+
+/// Header of a pack: the structure without file contents, and where to find them
+#[derive(Serialize, Deserialize, Debug)]
+struct Index {
+    structure: serde_json::Value,
+    entries: Vec<Entry>,
+}
+
+/// Kraglefile stored as an indexed binary container.
+///
+/// The layout is the magic, the layout version (u32 LE), the length of the index (u64 LE), the
+/// index as JSON, then an xz blob per file. Only the index is read when opening a pack, each file
+/// being decompressed when read.
+pub struct Pack<R> {
+    reader: R,
+    structure: Repo,
+    entries: Vec<Entry>,
+    by_path: HashMap<String, usize>,
+    /// Offset of the first blob
+    data_offset: u64,
+}
+
+impl Pack<File> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Pack::new(File::open(path)?)
+    }
+}
+
+impl<R: Read + Seek> Pack<R> {
+    /// Reads the header and the index of a pack
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0; 8];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|_| invalid("not a kpack file"))?;
+        if &magic != MAGIC {
+            return Err(invalid("not a kpack file"));
+        }
+        let mut word = [0; 4];
+        reader.read_exact(&mut word)?;
+        let pack_version = u32::from_le_bytes(word);
+        if pack_version != PACK_VERSION {
+            return Err(invalid(&format!(
+                "kpack layout version {} is not supported",
+                pack_version
+            )));
+        }
+        let mut length = [0; 8];
+        reader.read_exact(&mut length)?;
+        let length = u64::from_le_bytes(length);
+
+        let mut index = Vec::new();
+        (&mut reader).take(length).read_to_end(&mut index)?;
+        let mut index: Index =
+            serde_json::from_slice(&index).map_err(|e| invalid(&e.to_string()))?;
+        version::migrate(&mut index.structure)?;
+        let structure = serde_json::from_value(index.structure)?;
+
+        let by_path = index
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (entry.path.clone(), i))
+            .collect();
+        Ok(Pack {
+            reader,
+            structure,
+            entries: index.entries,
+            by_path,
+            data_offset: 20 + length,
+        })
+    }
+
+    /// Structure of the pack, file nodes having an empty content
+    pub fn structure(&self) -> &Repo {
+        &self.structure
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// Reads and decompresses the content of a single file
+    pub fn read<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Vec<u8>> {
+        let path = display(&Repo::normalized(path.as_ref()));
+        let Some(&index) = self.by_path.get(&path) else {
+            // Files without content have no blob
+            return self.structure.find(&path)?.decoded();
+        };
+        let entry = &self.entries[index];
+
+        self.reader
+            .seek(SeekFrom::Start(self.data_offset + entry.offset))?;
+        let mut blob = Vec::new();
+        (&mut self.reader)
+            .take(entry.length)
+            .read_to_end(&mut blob)?;
+        let mut data = Vec::new();
+        XzDecoder::new(&blob[..]).read_to_end(&mut data)?;
+        if format!("{:x}", md5::compute(&data)) != entry.original_md5 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: corrupted content in the kpack", path),
+            ));
+        }
+        Ok(data)
+    }
+
+    /// Loads the node at a path (`.` for the root) with the contents of its files
+    pub fn load<P: AsRef<Path>>(&mut self, path: P) -> io::Result<Repo> {
+        let path = Repo::normalized(path.as_ref());
+        let node = self.structure.find(&path)?;
+        let mut node: Repo = serde_json::from_value(serde_json::to_value(node)?)?;
+        let parent = path.parent().unwrap_or(Path::new(""));
+
+        let mut files = Vec::new();
+        for (file_path, _) in node.files() {
+            let file_path = display(&parent.join(file_path));
+            if self.by_path.contains_key(&file_path) {
+                files.push(file_path);
+            }
+        }
+        let mut contents = HashMap::new();
+        for file_path in files {
+            let data = self.read(&file_path)?;
+            contents.insert(file_path, data);
+        }
+
+        fill(&mut node, parent, &contents)?;
+        Ok(node)
+    }
+
+    /// Loads the whole structure with the contents of its files
+    pub fn into_repo(mut self) -> io::Result<Repo> {
+        self.load("")
+    }
+}
+
+/// Writes a structure as a pack
+pub fn write_pack<W: Write>(repo: &Repo, mut writer: W) -> io::Result<()> {
+    let mut structure = serde_json::to_value(repo)?;
+    let mut entries = Vec::new();
+    let mut blobs = Vec::new();
+    let mut offset = 0;
+    for (path, node) in repo.files() {
+        if !has_content(node) {
+            continue;
+        }
+        let data = node.decoded()?;
+        let mut blob = Vec::new();
+        XzEncoder::new(&data[..], 6).read_to_end(&mut blob)?;
+        entries.push(Entry {
+            path: display(&path),
+            offset,
+            length: blob.len() as u64,
+            original_size: data.len() as u64,
+            original_md5: format!("{:x}", md5::compute(&data)),
+        });
+        offset += blob.len() as u64;
+        blobs.push(blob);
+    }
+    strip_contents(&mut structure);
+
+    let index = serde_json::to_vec(&Index { structure, entries })?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&PACK_VERSION.to_le_bytes())?;
+    writer.write_all(&(index.len() as u64).to_le_bytes())?;
+    writer.write_all(&index)?;
+    for blob in blobs {
+        writer.write_all(&blob)?;
+    }
+    writer.flush()
+}
+
+fn display(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+/// Removes the contents of the file nodes of a raw structure, they are stored in the blobs
+fn strip_contents(node: &mut serde_json::Value) {
+    if let Some(fields) = node.as_object_mut() {
+        if fields.get("type").and_then(serde_json::Value::as_str) == Some("file") {
+            fields.remove("content");
+        }
+        if let Some(serde_json::Value::Array(children)) = fields.get_mut("children") {
+            children.iter_mut().for_each(strip_contents);
+        }
+    }
+}
+
+/// Sets the contents of the file nodes found in `contents`, encoded as their node declares
+fn fill(node: &mut Repo, parent: &Path, contents: &HashMap<String, Vec<u8>>) -> io::Result<()> {
+    let Some(name) = node.name() else {
+        return Ok(());
+    };
+    let path = Repo::normalized(&parent.join(name));
+    if let Repo::Directory { children, .. } = node {
+        for child in children {
+            fill(child, &path, contents)?;
+        }
+    } else if let Some(storage) = node.storage()
+        && let Some(data) = contents.get(&display(&path))
+        && let Repo::File { content, .. } = node
+    {
+        *content = storage.encode(data)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    const KRAGLEFILE: &str = r#"
+type: directory
+name: "."
+description: Rust project
+children:
+  - type: file
+    name: README.md
+    content: "kragle\n"
+    description: Project readme
+  - type: directory
+    name: static
+    children:
+      - type: file
+        name: logo.png
+        content: "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRPwP)<kx00000kk324dQrFx00ABb!7rp8AGY&-0RR9100dcD"
+        is_compressed: true
+      - type: file
+        name: robots.txt
+        check: exists
+  - type: pattern
+    name: "*.log"
+    forbidden: true
+"#;
+
+    fn pack() -> Vec<u8> {
+        let repo: Repo = serde_yml::from_str(KRAGLEFILE).unwrap();
+        let mut data = Vec::new();
+        write_pack(&repo, &mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn test_random_access() {
+        let data = pack();
+        assert!(data.starts_with(MAGIC));
+        let mut pack = Pack::new(Cursor::new(data)).unwrap();
+
+        assert_eq!(pack.entries().len(), 2);
+        assert!(matches!(
+            pack.structure().find("README.md").unwrap(),
+            Repo::File { content, .. } if content.is_empty()
+        ));
+        assert_eq!(pack.read("static/logo.png").unwrap(), b"\x89PNG\xff\x00");
+        assert_eq!(pack.read("./README.md").unwrap(), b"kragle\n");
+        assert_eq!(pack.read("static/robots.txt").unwrap(), b"");
+        assert!(pack.read("missing.txt").is_err());
+
+        let static_dir = pack.load("static").unwrap();
+        assert_eq!(
+            static_dir
+                .find("static/logo.png")
+                .unwrap()
+                .decoded()
+                .unwrap(),
+            b"\x89PNG\xff\x00"
+        );
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let repo = Pack::new(Cursor::new(pack())).unwrap().into_repo().unwrap();
+        let original: Repo = serde_yml::from_str(KRAGLEFILE).unwrap();
+        for ((path, node), (original_path, original_node)) in
+            repo.nodes().into_iter().zip(original.nodes())
+        {
+            assert_eq!(path, original_path);
+            if has_content(original_node) {
+                assert_eq!(node.decoded().unwrap(), original_node.decoded().unwrap());
+            }
+        }
+        assert!(matches!(
+            repo.find("README.md").unwrap(),
+            Repo::File { description: Some(_), is_compressed: None, content, .. } if content == "kragle\n"
+        ));
+
+        let mut corrupted = pack();
+        let last = corrupted.len() - 20;
+        corrupted[last] ^= 0xff;
+        assert!(
+            Pack::new(Cursor::new(corrupted))
+                .unwrap()
+                .into_repo()
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::codec::Storage;
+use crate::config::ExportConfig;
+use crate::diff::has_content;
+use crate::repo::Repo;
+
+/// Updates a structure from the folder it was exported from.
+///
+/// Files whose content changed get a new content, size and md5, nodes missing from the folder are
+/// removed and new files or directories are appended, `storage` deciding how they are stored.
+/// Everything else is kept as written: the order of the nodes, descriptions, dependencies, checks,
+/// patterns, forbidden nodes, file nodes without content and missing optional files.
+///
+/// Paths ignored by the export configuration of the folder count as missing.
+pub fn refresh(repo: &mut Repo, folder: &Path, storage: Storage) -> io::Result<()> {
+    let config = ExportConfig::load(folder)?;
+    match repo {
+        Repo::Directory { children, .. } => {
+            refresh_children(children, folder, Path::new(""), storage, &config)
+        }
+        _ => Err(io::Error::other(
+            "The root of the kraglefile is not a directory",
+        )),
+    }
+}
+
+fn refresh_children(
+    children: &mut Vec<Repo>,
+    folder: &Path,
+    relative: &Path,
+    storage: Storage,
+    config: &ExportConfig,
+) -> io::Result<()> {
+    let mut kept = Vec::new();
+    for mut child in children.drain(..) {
+        let Some(name) = child.name().map(str::to_string) else {
+            continue;
+        };
+        let path = folder.join(&name);
+        let child_relative = relative.join(&name);
+        let ignored = config.is_ignored(&child_relative);
+        if child.is_forbidden() || !matches!(child, Repo::Directory { .. } | Repo::File { .. }) {
+            kept.push(child);
+            continue;
+        }
+
+        let is_file = matches!(child, Repo::File { .. });
+        let is_optional = matches!(
+            child,
+            Repo::File {
+                is_optional: Some(true),
+                ..
+            }
+        );
+        if let Repo::Directory { children, .. } = &mut child
+            && path.is_dir()
+            && !ignored
+        {
+            refresh_children(children, &path, &child_relative, storage, config)?;
+        } else if is_file && path.is_file() && !ignored {
+            refresh_file(&mut child, &path)?;
+        } else if !is_file || (path.exists() && !ignored) || (!is_optional && has_content(&child)) {
+            // Missing, or replaced by a node of another type that is added back below
+            continue;
+        }
+        kept.push(child);
+    }
+
+    let known: HashSet<String> = kept
+        .iter()
+        .filter(|child| !matches!(child, Repo::Pattern { .. }))
+        .filter_map(|child| child.name().map(str::to_string))
+        .collect();
+    let mut entries = fs::read_dir(folder)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+    for entry in entries {
+        let entry_relative = relative.join(entry.file_name());
+        if known.contains(entry.file_name().to_string_lossy().as_ref())
+            || config.is_ignored(&entry_relative)
+        {
+            continue;
+        }
+        kept.push(Repo::from_entry(
+            &entry.path(),
+            &entry_relative,
+            storage,
+            relative.components().count() + 1,
+            config,
+        )?);
+    }
+
+    *children = kept;
+    Ok(())
+}
+
+/// Updates the content, size and md5 of a file node whose file changed
+fn refresh_file(node: &mut Repo, path: &Path) -> io::Result<()> {
+    let data = fs::read(path)?;
+    if !has_content(node) || node.decoded()? == data {
+        return Ok(());
+    }
+
+    // Files keep their storage, binary files being compressed
+    let storage = node.storage().unwrap_or_default();
+    let storage = if Repo::is_binary(&path.to_path_buf()) {
+        storage.compressed()
+    } else {
+        storage
+    };
+    node.set_content(&data, storage)?;
+    if let Repo::File {
+        original_size,
+        original_md5,
+        ..
+    } = node
+    {
+        *original_size = Some(data.len() as u64);
+        *original_md5 = Some(format!("{:x}", md5::compute(&data)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_refresh() {
+        let temp_dir = TempDir::new().unwrap();
+        let folder = temp_dir.path();
+        fs::create_dir_all(folder.join("src")).unwrap();
+        fs::write(folder.join("src/lib.rs"), "pub mod repo;\n").unwrap();
+        fs::write(folder.join("src/repo.rs"), "pub enum Repo {}\n").unwrap();
+        fs::write(folder.join("README.md"), "# kragle\n").unwrap();
+        fs::write(folder.join("Cargo.toml"), "[package]\n").unwrap();
+
+        let mut repo: Repo = serde_yml::from_str(
+            r##"
+type: directory
+name: "."
+description: Rust project
+dependencies:
+  - ./base.yaml
+children:
+  - type: file
+    name: README.md
+    content: "# kragle\n"
+    description: Project readme
+  - type: directory
+    name: src
+    children:
+      - type: file
+        name: lib.rs
+        content: "pub mod old;\n"
+        original_size: 13
+        is_optional: false
+      - type: file
+        name: main.rs
+        content: "fn main() {}\n"
+  - type: file
+    name: CHANGELOG.md
+    content: ""
+    is_optional: true
+  - type: file
+    name: LICENSE
+    content: "MIT\n"
+  - type: pattern
+    name: "*.log"
+    forbidden: true
+"##,
+        )
+        .unwrap();
+
+        refresh(&mut repo, folder, Storage::default()).unwrap();
+
+        let paths: Vec<String> = repo
+            .nodes()
+            .into_iter()
+            .map(|(path, _)| path.to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                "",
+                "README.md",
+                "src",
+                "src/lib.rs",
+                "src/repo.rs",
+                "CHANGELOG.md",
+                "*.log",
+                "Cargo.toml"
+            ]
+        );
+
+        let Repo::Directory {
+            description,
+            dependencies,
+            ..
+        } = &repo
+        else {
+            panic!("not a directory");
+        };
+        assert_eq!(description.as_deref(), Some("Rust project"));
+        assert_eq!(
+            dependencies.as_ref().unwrap(),
+            &vec!["./base.yaml".to_string()]
+        );
+        assert!(matches!(
+            repo.find("README.md").unwrap(),
+            Repo::File {
+                description: Some(_),
+                original_md5: None,
+                ..
+            }
+        ));
+
+        let lib = repo.find("src/lib.rs").unwrap();
+        assert_eq!(lib.decoded().unwrap(), b"pub mod repo;\n");
+        assert!(matches!(
+            lib,
+            Repo::File {
+                original_size: Some(14),
+                original_md5: Some(_),
+                is_optional: Some(false),
+                ..
+            }
+        ));
+    }
+}
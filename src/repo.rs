@@ -1,70 +1,280 @@
+use std::collections::BTreeMap;
 use std::env;
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
 use md5;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use xz2::read::{XzDecoder, XzEncoder};
 
+use crate::assertion::{Assertion, Check};
+use crate::codec::{Codec, Encoding, Storage};
+use crate::config::ExportConfig;
+use crate::content_schema::ContentSchema;
 use crate::dialog::{self, Dialog};
+use crate::format::Format;
+use crate::located::{Located, check_fields};
+use crate::pack::write_pack;
+use crate::validation::{Issue, IssueKind, Severity};
+use crate::version::{self, CURRENT_VERSION, Deprecation};
 
-#[derive(Serialize, Deserialize, Debug)]
+/// Node of a kraglefile: the structure of a folder and the content of its files
+#[derive(Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "snake_case")]
-#[serde(tag = "type")]
+#[serde(tag = "type", deny_unknown_fields)]
 pub enum Repo {
+    /// Directory and its children
     Directory {
+        /// Version of the kraglefile format, set on the root node
+        #[serde(skip_serializing_if = "Option::is_none")]
+        kragle_version: Option<u64>,
         name: String,
+        #[serde(default)]
         children: Vec<Repo>,
+        /// Kraglefiles (paths, names or URIs) imported into the same folder before this one
         dependencies: Option<Vec<String>>,
         description: Option<String>,
+        /// Values describing the structure (e.g. the project name), set on the root node
+        #[serde(skip_serializing_if = "Option::is_none")]
+        parameters: Option<BTreeMap<String, serde_json::Value>>,
+        /// The path must not exist
+        #[serde(skip_serializing_if = "Option::is_none")]
+        forbidden: Option<bool>,
+        /// How serious a failed check of the node is (default `error`)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        severity: Option<Severity>,
     },
+    /// File and its content
     File {
         name: String,
+        /// Content of the file, encoded as `codec` and `encoding` tell
+        #[serde(default)]
         content: String,
+        /// Size in bytes of the decoded content
         original_size: Option<u64>,
+        /// md5 of the decoded content
         original_md5: Option<String>,
+        /// Compression of the content (default `none`)
+        codec: Option<Codec>,
+        /// Encoding of the content (default `utf8` without codec, `base85` otherwise)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        encoding: Option<Encoding>,
+        /// Deprecated: base85-encoded xz content, replaced by `codec` and `encoding` in version 3
+        #[serde(skip_serializing_if = "Option::is_none")]
         is_compressed: Option<bool>,
+        /// The file may be missing or customized
         is_optional: Option<bool>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        check: Option<Check>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        assertions: Option<Vec<Assertion>>,
+        /// JSON Schema the content of the file must follow
+        #[serde(skip_serializing_if = "Option::is_none")]
+        schema: Option<ContentSchema>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        /// The path must not exist
+        #[serde(skip_serializing_if = "Option::is_none")]
+        forbidden: Option<bool>,
+        /// How serious a failed check of the node is (default `error`)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        severity: Option<Severity>,
     },
+    /// Matches the entries of a directory against a glob pattern (e.g. `*.rs`, `LICENSE*`)
+    Pattern {
+        name: String,
+        /// Minimum number of matching entries (default 1)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        min: Option<usize>,
+        /// Maximum number of matching entries
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max: Option<usize>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        assertions: Option<Vec<Assertion>>,
+        /// No entry may match the pattern
+        #[serde(skip_serializing_if = "Option::is_none")]
+        forbidden: Option<bool>,
+        /// How serious a failed check of the node is (default `error`)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        severity: Option<Severity>,
+    },
+    /// Empty node
     None {},
 }
 
+/// Path standing for stdin or stdout
+pub const STDIO: &str = "-";
+
+/// Raw kraglefile along with where it was read from
+#[derive(Debug)]
+pub struct Source {
+    /// Resolved location, `-` for stdin
+    pub uri: String,
+    pub data: Vec<u8>,
+    pub format: Format,
+}
+
+impl Source {
+    /// The format comes from the extension of the location, then from the HTTP `Content-Type`,
+    /// then from the content itself
+    pub fn new(uri: String, data: Vec<u8>, content_type: Option<&str>) -> Self {
+        let format = Format::from_extension(&uri)
+            .or_else(|| content_type.and_then(Format::from_content_type))
+            .unwrap_or_else(|| Format::sniff(&data));
+        Source { uri, data, format }
+    }
+
+    fn invalid(&self, message: String) -> io::Error {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", self.uri, message),
+        )
+    }
+}
+
 impl Repo {
     pub fn new(uri: &String) -> io::Result<Self> {
         let dialog = dialog::Dialog::new(format!("Loading uri {}", uri).to_string());
         dialog.start_print(dialog::Status::Unknown);
 
-        let resolve_uri = Self::whereis(uri)?;
+        match Self::fetch(uri).and_then(|source| Self::parse_migrated(&source)) {
+            Ok((repo, deprecations)) => {
+                dialog.end_print(dialog::Status::Ok);
+                for summary in version::summarize(&deprecations) {
+                    writeln!(
+                        io::stderr(),
+                        "{} {}, run `kragle migrate`",
+                        dialog::Status::Warning.colorized(),
+                        summary
+                    )?;
+                }
+                Ok(repo)
+            }
+            Err(e) => {
+                dialog.end_print(dialog::Status::Failed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Loads a structure like `new`, without printing anything
+    pub fn load(uri: &String) -> io::Result<Self> {
+        Self::parse(&Self::fetch(uri)?)
+    }
 
-        if resolve_uri.starts_with("http://") || resolve_uri.starts_with("https://") {
-            let response = reqwest::blocking::get(resolve_uri).unwrap();
-            let bytes = response.bytes().unwrap();
-            dialog.end_print(dialog::Status::Ok);
-            Ok(serde_yml::from_slice(&bytes).unwrap())
+    /// Reads the raw kraglefile behind a path, a name, an URI or `-` (stdin)
+    pub fn fetch(uri: &String) -> io::Result<Source> {
+        if uri == STDIO {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data)?;
+            return Ok(Source::new(uri.clone(), data, None));
+        }
+
+        let resolve_uri = Self::whereis(uri)?;
+        if Self::is_remote(&resolve_uri) {
+            let response = reqwest::blocking::get(&resolve_uri).map_err(io::Error::other)?;
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let data = response.bytes().map_err(io::Error::other)?.to_vec();
+            Ok(Source::new(resolve_uri, data, content_type.as_deref()))
         } else {
-            let file = File::open(&resolve_uri).unwrap();
-            let content = io::read_to_string(&file).unwrap();
-            if resolve_uri.ends_with(".json") {
-                dialog.end_print(dialog::Status::Ok);
-                Ok(serde_json::from_str(&content)?)
-            } else if resolve_uri.ends_with(".yaml") || resolve_uri.ends_with(".yml") {
-                dialog.end_print(dialog::Status::Ok);
+            let data = fs::read(&resolve_uri)?;
+            Ok(Source::new(resolve_uri, data, None))
+        }
+    }
 
-                Ok(serde_yml::from_str(&content).unwrap())
-            } else {
-                dialog.end_print(dialog::Status::Failed);
-                Err(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    "Unsupported file type",
-                ))
+    /// Parses a raw kraglefile.
+    ///
+    /// Unknown node types and fields are rejected with their line and column.
+    pub fn parse(source: &Source) -> io::Result<Self> {
+        Ok(Self::parse_migrated(source)?.0)
+    }
+
+    /// Parses a kraglefile like `parse`, migrating older versions of the format to the current one
+    /// and returning the fields that changed
+    pub fn parse_migrated(source: &Source) -> io::Result<(Self, Vec<Deprecation>)> {
+        let mut value = Self::parse_value(source)?;
+        let deprecations =
+            version::migrate(&mut value).map_err(|e| source.invalid(e.to_string()))?;
+
+        match serde_json::from_value(value) {
+            Ok(repo) => Ok((repo, deprecations)),
+            Err(e) => {
+                // Errors raised inside a node lose their position, look for the faulty field instead
+                let located = if source.format.is_yaml_compatible() {
+                    Located::parse(&String::from_utf8_lossy(&source.data))
+                } else {
+                    Err(String::new())
+                };
+                Err(match located.map(|located| check_fields(&located)) {
+                    Ok(errors) if !errors.is_empty() => source.invalid(format!(
+                        "{}:{}: {}",
+                        errors[0].position.line, errors[0].position.column, errors[0].message
+                    )),
+                    _ => source.invalid(e.to_string()),
+                })
             }
         }
     }
 
+    /// Parses a kraglefile into a generic value, as written (no migration)
+    pub fn parse_value(source: &Source) -> io::Result<serde_json::Value> {
+        source
+            .format
+            .parse(&source.data)
+            .map_err(|e| source.invalid(e.to_string()))
+    }
+
+    /// Writes the structure as JSON, YAML, TOML or a pack according to the extension of `path`, `-`
+    /// writing YAML to stdout
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        match Format::from_extension(&path.to_string_lossy()) {
+            Some(format) => self.save_as(path, format),
+            None if path == Path::new(STDIO) => self.save_as(path, Format::Yaml),
+            None => Err(io::Error::other(format!(
+                "Unsupported file type: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    /// Writes the structure in a given format, `-` writing to stdout
+    pub fn save_as<P: AsRef<Path>>(&self, path: P, format: Format) -> io::Result<()> {
+        let path = path.as_ref();
+        let data = match format {
+            Format::Pack => {
+                let mut data = Vec::new();
+                write_pack(self, &mut data)?;
+                data
+            }
+            _ => format.to_string(self)?.into_bytes(),
+        };
+        if path == Path::new(STDIO) {
+            io::stdout().write_all(&data)
+        } else {
+            fs::write(path, data)
+        }
+    }
+
+    pub fn is_remote(uri: &str) -> bool {
+        uri.starts_with("http://") || uri.starts_with("https://")
+    }
+
     pub fn whereis(uri: &String) -> io::Result<String> {
-        // 1. Check if the uri is a local file
-        if uri.starts_with('/') || uri.starts_with("./") || uri.starts_with(".\\") {
+        // 1. Check if the uri is a local file, an URL or stdin
+        if uri == STDIO
+            || uri.starts_with('/')
+            || uri.starts_with("./")
+            || uri.starts_with(".\\")
+            || Self::is_remote(uri)
+        {
             return Ok(uri.to_string());
         }
 
@@ -84,20 +294,61 @@ pub fn whereis(uri: &String) -> io::Result<String> {
             }
         }
 
-        // 3. Assume the uri is on gihub
+        // 3. Assume the uri is on gihub (names listed in the manifest have an extension)
+        let extension = if Format::from_extension(uri).is_some() {
+            ""
+        } else {
+            ".yaml"
+        };
         Ok(format!(
-            "https://github.com/Jxtopher/kragle/blob/main/kraglefile/{}.yaml?raw=true",
-            &uri,
+            "https://github.com/Jxtopher/kragle/blob/main/kraglefile/{}{}?raw=true",
+            &uri, extension,
         ))
     }
 
     /// Converts a folder and its tree into a JSON structure.
+    ///
+    /// The root folder (`depth` 0) is exported following its `kragle.toml` or `.kragle.yaml`.
     pub fn from_folder<P: AsRef<Path>>(
         path: P,
-        is_compressed: bool,
+        storage: Storage,
         depth: usize,
     ) -> io::Result<Self> {
         let path = path.as_ref();
+        if depth > 0 {
+            return Self::from_folder_with(
+                path,
+                Path::new(""),
+                storage,
+                depth,
+                &ExportConfig::default(),
+            );
+        }
+
+        let config = ExportConfig::load(path)?;
+        let mut repo = Self::from_folder_with(path, Path::new(""), storage, 0, &config)?;
+        if let Repo::Directory {
+            dependencies,
+            description,
+            parameters,
+            ..
+        } = &mut repo
+        {
+            *description = config.description;
+            *dependencies = (!config.dependencies.is_empty()).then_some(config.dependencies);
+            *parameters = (!config.parameters.is_empty()).then_some(config.parameters);
+        }
+        Ok(repo)
+    }
+
+    /// Converts a folder, `relative` being its path in the exported folder
+    pub(crate) fn from_folder_with(
+        path: &Path,
+        relative: &Path,
+        storage: Storage,
+        depth: usize,
+        config: &ExportConfig,
+    ) -> io::Result<Self> {
         let name = if depth == 0 {
             ".".to_string()
         } else {
@@ -108,57 +359,112 @@ pub fn from_folder<P: AsRef<Path>>(
         let mut children = Vec::new();
         for entry in fs::read_dir(path)? {
             let entry = entry?;
-            let entry_path = entry.path();
-            if entry_path.is_dir() {
-                children.push(Repo::from_folder(&entry_path, is_compressed, depth + 1)?);
-            } else {
-                // Read file content for md5 and contents
-                let mut file = File::open(&entry_path)?;
-                let mut buf = Vec::new();
-                file.read_to_end(&mut buf)?;
-
-                let original_md5 = format!("{:x}", md5::compute(&buf));
-                let original_size = buf.len() as u64;
-                let content = if is_compressed || Self::is_binary(&entry_path) {
-                    let mut xz = XzEncoder::new(&buf[..], 6);
-                    let mut compressed = Vec::new();
-                    xz.read_to_end(&mut compressed)?;
-                    base85::encode(&compressed)
-                } else {
-                    String::from_utf8_lossy(&buf).to_string()
-                };
-
-                children.push(Repo::File {
-                    name: entry.file_name().to_string_lossy().to_string(),
-                    original_size: Some(original_size),
-                    original_md5: Some(original_md5),
-                    is_compressed: Some(is_compressed),
-                    content,
-                    is_optional: Some(false),
-                });
+            let entry_relative = relative.join(entry.file_name());
+            if !config.is_ignored(&entry_relative) {
+                children.push(Repo::from_entry(
+                    &entry.path(),
+                    &entry_relative,
+                    storage,
+                    depth + 1,
+                    config,
+                )?);
             }
         }
 
         Ok(Repo::Directory {
+            kragle_version: (depth == 0).then_some(CURRENT_VERSION),
             name,
             children,
             dependencies: None,
             description: None,
+            parameters: None,
+            forbidden: None,
+            severity: None,
         })
     }
 
-    pub fn get_dependency(&self, uri: &String) -> io::Result<Repo> {
-        if uri.starts_with("http://") || uri.starts_with("https://") {
-            let response = reqwest::blocking::get(uri).unwrap();
-            let bytes = response.bytes().unwrap();
-            Ok(serde_yml::from_slice(&bytes).unwrap())
+    /// Converts a file or a folder found while exporting
+    pub(crate) fn from_entry(
+        path: &Path,
+        relative: &Path,
+        storage: Storage,
+        depth: usize,
+        config: &ExportConfig,
+    ) -> io::Result<Self> {
+        if path.is_dir() {
+            return Self::from_folder_with(path, relative, storage, depth, config);
+        }
+        let storage = if config.is_compressed(relative) {
+            storage.compressed()
         } else {
-            Repo::new(uri)
+            storage
+        };
+        let mut node = Repo::from_file(path, storage)?;
+        if let Repo::File { is_optional, .. } = &mut node {
+            *is_optional = Some(config.is_optional(relative));
         }
+        Ok(node)
+    }
+
+    /// Converts a single file into a file node stored as asked, binary files being always
+    /// compressed unless the storage is chosen from their content
+    pub fn from_file<P: AsRef<Path>>(path: P, storage: Storage) -> io::Result<Self> {
+        let path = path.as_ref();
+        let buf = fs::read(path)?;
+        let storage = if Self::is_binary(&path.to_path_buf()) && !storage.auto {
+            storage.compressed()
+        } else {
+            storage
+        };
+        let (storage, content) = storage.choose(&buf)?;
+        let (codec, encoding) = storage.fields();
+
+        Ok(Repo::File {
+            name: path
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            original_size: Some(buf.len() as u64),
+            original_md5: Some(format!("{:x}", md5::compute(&buf))),
+            codec,
+            encoding,
+            is_compressed: None,
+            content,
+            is_optional: Some(false),
+            check: None,
+            assertions: None,
+            schema: None,
+            description: None,
+            forbidden: None,
+            severity: None,
+        })
+    }
+
+    pub fn get_dependency(&self, uri: &String) -> io::Result<Repo> {
+        Repo::new(uri)
     }
 
     /// Recreates a folder and file tree from a JSON structure.
     pub fn to_folder<P: AsRef<Path>>(&self, target_path: P, dialog: &mut Dialog) -> io::Result<()> {
+        self.write_folder(target_path.as_ref(), dialog, true)
+    }
+
+    /// Recreates the subtree of the node only, without importing the dependencies of its
+    /// directories.
+    pub fn restore<P: AsRef<Path>>(&self, target_path: P, dialog: &mut Dialog) -> io::Result<()> {
+        self.write_folder(target_path.as_ref(), dialog, false)
+    }
+
+    fn write_folder(
+        &self,
+        target_path: &Path,
+        dialog: &mut Dialog,
+        with_dependencies: bool,
+    ) -> io::Result<()> {
+        if self.is_forbidden() {
+            return Ok(());
+        }
+
         match self {
             Repo::Directory {
                 name,
@@ -167,18 +473,18 @@ pub fn to_folder<P: AsRef<Path>>(&self, target_path: P, dialog: &mut Dialog) ->
                 ..
             } => {
                 match dependencies {
-                    None => {} // No dependencies
-                    Some(dependencies) => {
+                    Some(dependencies) if with_dependencies => {
                         for dependency in dependencies {
                             dialog.set_msg(format!("Dependancie found: {}", dependency));
                             dialog.end_print(dialog::Status::Ok);
                             let dependent_repo = self.get_dependency(dependency)?;
-                            dependent_repo.to_folder(target_path.as_ref(), dialog)?;
+                            dependent_repo.to_folder(target_path, dialog)?;
                         }
                     }
+                    _ => {} // No dependencies, or not imported
                 }
 
-                let dir_path = target_path.as_ref().join(name);
+                let dir_path = target_path.join(name);
                 if !Path::new(&dir_path).exists() {
                     dialog.set_msg(format!("Created directory: {}", dir_path.display()));
                     dialog.spinner();
@@ -186,27 +492,14 @@ pub fn to_folder<P: AsRef<Path>>(&self, target_path: P, dialog: &mut Dialog) ->
                 }
 
                 for child in children {
-                    child.to_folder(&dir_path, dialog)?;
+                    child.write_folder(&dir_path, dialog, with_dependencies)?;
                 }
             }
             Repo::File {
-                name,
-                is_compressed,
-                content,
-                original_md5,
-                ..
+                name, original_md5, ..
             } => {
-                let file_path = target_path.as_ref().join(name);
-                let file_content = match is_compressed {
-                    Some(true) => {
-                        let decoded = base85::decode(content).map_err(io::Error::other)?;
-                        let mut xz = XzDecoder::new(&decoded[..]);
-                        let mut decompressed = Vec::new();
-                        xz.read_to_end(&mut decompressed)?;
-                        decompressed
-                    }
-                    Some(false) | None => content.as_bytes().to_vec(),
-                };
+                let file_path = target_path.join(name);
+                let file_content = self.decoded()?;
 
                 dialog.set_msg(format!("Created file: {}", file_path.display()));
                 dialog.spinner();
@@ -248,51 +541,43 @@ pub fn to_folder<P: AsRef<Path>>(&self, target_path: P, dialog: &mut Dialog) ->
                 //     println!("Created file: {}", file_path.display());
                 // }
             }
-            Repo::None { .. } => {}
+            Repo::Pattern { .. } | Repo::None { .. } => {}
         }
         Ok(())
     }
 
-    pub fn display_tree(&self, prefix: &str, last: bool) -> io::Result<()> {
-        match self {
-            Repo::Directory { name, children, .. } => {
-                println!(
-                    "{}{} \x1b[34m{}\x1b[0m",
-                    prefix,
-                    if last { "└──" } else { "├──" },
-                    name
-                );
-                let new_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
-                let count = children.len();
-                for (i, child) in children.iter().enumerate() {
-                    child.display_tree(&new_prefix, i == count - 1)?;
-                }
-            }
-            Repo::File { name, .. } => {
-                println!("{}{} {}", prefix, if last { "└──" } else { "├──" }, name);
-            }
-            Repo::None { .. } => {}
-        }
-        Ok(())
+    /// Validates a folder against the structure, returns the issues found
+    pub fn validated<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<Issue<'_>>> {
+        let mut issues = Vec::new();
+        self.collect_issues(path.as_ref(), &mut issues)?;
+        Ok(issues)
     }
 
-    pub fn validated<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let path = path.as_ref();
+    fn collect_issues<'a>(&'a self, path: &Path, issues: &mut Vec<Issue<'a>>) -> io::Result<()> {
+        if self.is_forbidden() {
+            return self.collect_forbidden(path, issues);
+        }
 
         match self {
             Repo::Directory { name, children, .. } => {
                 let dir_path = path.join(name);
                 // Check if directory exists and is a directory
-                let meta = fs::metadata(&dir_path)?;
+                let meta = match fs::metadata(&dir_path) {
+                    Ok(meta) => meta,
+                    Err(e) => {
+                        let message = format!("Directory {} not found: {}", dir_path.display(), e);
+                        issues.push(Issue::new(self, dir_path, IssueKind::Missing, message));
+                        return Ok(());
+                    }
+                };
                 if !meta.is_dir() {
-                    return Err(io::Error::other(format!(
-                        "{} is not a directory",
-                        dir_path.display()
-                    )));
+                    let message = format!("{} is not a directory", dir_path.display());
+                    issues.push(Issue::new(self, dir_path, IssueKind::WrongType, message));
+                    return Ok(());
                 }
                 // Recursively validate children
                 for child in children {
-                    child.validated(&dir_path)?;
+                    child.collect_issues(&dir_path, issues)?;
                 }
                 Ok(())
             }
@@ -301,6 +586,9 @@ pub fn validated<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
                 original_size,
                 original_md5,
                 is_optional,
+                check,
+                assertions,
+                schema,
                 ..
             } => {
                 let file_path = path.join(name);
@@ -310,68 +598,214 @@ pub fn validated<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
                     match is_optional {
                         Some(true) => return Ok(()), // If file is optional, skip missing files
                         Some(false) | None => {
-                            println!("File {} not found: {}", file_path.display(), e);
+                            let message = format!("File {} not found: {}", file_path.display(), e);
+                            issues.push(Issue::new(self, file_path, IssueKind::Missing, message));
                             return Ok(());
                         }
                     }
                 }
                 let meta = meta?;
                 if !meta.is_file() {
-                    println!("File {} is not a file", file_path.display());
-                    // return Err(io::Error::new(
-                    //     io::ErrorKind::Other,
-                    //     format!("{} is not a file", file_path.display()),
-                    // ));
+                    let message = format!("File {} is not a file", file_path.display());
+                    issues.push(Issue::new(self, file_path, IssueKind::WrongType, message));
+                    return Ok(());
+                }
+
+                let check = check.unwrap_or_default();
+
+                // Check file size
+                if check >= Check::Size
+                    && let Some(original_size) = original_size
+                    && meta.len() != *original_size
+                {
+                    let message = format!(
+                        "File {} size mismatch: expected {}, found {}",
+                        file_path.display(),
+                        original_size,
+                        meta.len()
+                    );
+                    issues.push(Issue::new(
+                        self,
+                        file_path.clone(),
+                        IssueKind::Mismatch,
+                        message,
+                    ));
+                }
+
+                if check == Check::Exists && assertions.is_none() && schema.is_none() {
+                    return Ok(());
                 }
+                let file_data = fs::read(&file_path)?;
 
-                match original_size {
-                    Some(original_size) => {
-                        // Check file size
-                        if meta.len() != *original_size {
-                            println!(
-                                "File {} size mismatch: expected {}, found {}",
+                // Check file md5
+                if check >= Check::Hash
+                    && let Some(original_md5) = original_md5
+                {
+                    let computed_md5 = format!("{:x}", md5::compute(&file_data));
+                    if &computed_md5 != original_md5 {
+                        let message = format!(
+                            "File {} md5 mismatch: expected {}, found {}",
+                            file_path.display(),
+                            original_md5,
+                            computed_md5
+                        );
+                        issues.push(Issue::new(
+                            self,
+                            file_path.clone(),
+                            IssueKind::Mismatch,
+                            message,
+                        ));
+                    }
+                }
+
+                // Check file content
+                if check >= Check::Content {
+                    match self.decoded() {
+                        Ok(expected) if file_data != expected => {
+                            let message = format!("File {} content mismatch", file_path.display());
+                            issues.push(Issue::new(
+                                self,
+                                file_path.clone(),
+                                IssueKind::Mismatch,
+                                message,
+                            ));
+                        }
+                        Ok(_) => {}
+                        Err(e) => {
+                            let message = format!(
+                                "File {} content of the kraglefile does not decode: {}",
                                 file_path.display(),
-                                original_size,
-                                meta.len()
+                                e
                             );
-                            // return Err(io::Error::new(
-                            //     io::ErrorKind::Other,
-                            //     format!(
-                            //         "File {} size mismatch: expected {}, found {}",
-                            //         file_path.display(),
-                            //         original_size,
-                            //         meta.len()
-                            //     ),
-                            // ));
+                            issues.push(Issue::new(
+                                self,
+                                file_path.clone(),
+                                IssueKind::Undecodable,
+                                message,
+                            ));
                         }
                     }
-                    None => todo!(),
                 }
 
-                // Check file md5
-                match original_md5 {
-                    Some(original_md5) => {
-                        let file_data = fs::read(&file_path)?;
-                        let computed_md5 = format!("{:x}", md5::compute(&file_data));
-                        if &computed_md5 != original_md5 {
-                            println!(
-                                "File {} md5 mismatch: expected {}, found {}",
+                for assertion in assertions.iter().flatten() {
+                    if let Err(reason) = assertion.check(&file_data) {
+                        let message = format!(
+                            "File {} assertion failed: {} ({})",
+                            file_path.display(),
+                            assertion,
+                            reason
+                        );
+                        issues.push(Issue::new(
+                            self,
+                            file_path.clone(),
+                            IssueKind::Assertion,
+                            message,
+                        ));
+                    }
+                }
+
+                if let Some(schema) = schema {
+                    let messages = match schema.check(&file_path, &file_data) {
+                        Ok(violations) => violations
+                            .into_iter()
+                            .map(|violation| {
+                                format!(
+                                    "File {} schema violation: {}",
+                                    file_path.display(),
+                                    violation
+                                )
+                            })
+                            .collect(),
+                        Err(e) => vec![format!(
+                            "File {} schema check failed: {}",
+                            file_path.display(),
+                            e
+                        )],
+                    };
+                    for message in messages {
+                        issues.push(Issue::new(
+                            self,
+                            file_path.clone(),
+                            IssueKind::Schema,
+                            message,
+                        ));
+                    }
+                }
+
+                Ok(())
+            }
+            Repo::Pattern {
+                name,
+                min,
+                max,
+                assertions,
+                ..
+            } => {
+                let matched = Self::matched_entries(path, name)?;
+                let pattern_path = path.join(name);
+                let found = if matched.is_empty() {
+                    String::new()
+                } else {
+                    format!(": {}", matched.join(", "))
+                };
+                // A pattern node expects at least one entry unless told otherwise
+                let min = min.unwrap_or(1);
+                if matched.len() < min {
+                    let message = format!(
+                        "Pattern {} matched {} entries, expected at least {}{}",
+                        pattern_path.display(),
+                        matched.len(),
+                        min,
+                        found
+                    );
+                    issues.push(Issue::new(
+                        self,
+                        pattern_path.clone(),
+                        IssueKind::Pattern,
+                        message,
+                    ));
+                }
+                if let Some(max) = max
+                    && matched.len() > *max
+                {
+                    let message = format!(
+                        "Pattern {} matched {} entries, expected at most {}{}",
+                        pattern_path.display(),
+                        matched.len(),
+                        max,
+                        found
+                    );
+                    issues.push(Issue::new(
+                        self,
+                        pattern_path.clone(),
+                        IssueKind::Pattern,
+                        message,
+                    ));
+                }
+
+                for file_name in &matched {
+                    let file_path = path.join(file_name);
+                    if assertions.is_none() || !file_path.is_file() {
+                        continue;
+                    }
+                    let file_data = fs::read(&file_path)?;
+                    for assertion in assertions.iter().flatten() {
+                        if let Err(reason) = assertion.check(&file_data) {
+                            let message = format!(
+                                "File {} (pattern {}) assertion failed: {} ({})",
                                 file_path.display(),
-                                original_md5,
-                                computed_md5
+                                name,
+                                assertion,
+                                reason
                             );
-                            // return Err(io::Error::new(
-                            //     io::ErrorKind::Other,
-                            //     format!(
-                            //         "File {} md5 mismatch: expected {}, found {}",
-                            //         file_path.display(),
-                            //         original_md5,
-                            //         computed_md5
-                            //     ),
-                            // ));
+                            issues.push(Issue::new(
+                                self,
+                                file_path.clone(),
+                                IssueKind::Assertion,
+                                message,
+                            ));
                         }
                     }
-                    None => todo!(),
                 }
 
                 Ok(())
@@ -380,7 +814,212 @@ pub fn validated<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
         }
     }
 
-    fn is_binary(path: &PathBuf) -> bool {
+    /// Name of the node, `None` for empty nodes
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Repo::Directory { name, .. } | Repo::File { name, .. } | Repo::Pattern { name, .. } => {
+                Some(name)
+            }
+            Repo::None {} => None,
+        }
+    }
+
+    /// Whether the node describes a path that must not exist
+    pub fn is_forbidden(&self) -> bool {
+        match self {
+            Repo::Directory { forbidden, .. }
+            | Repo::File { forbidden, .. }
+            | Repo::Pattern { forbidden, .. } => forbidden.unwrap_or(false),
+            Repo::None {} => false,
+        }
+    }
+
+    /// How serious a failed check of the node is
+    pub fn severity(&self) -> Severity {
+        match self {
+            Repo::Directory { severity, .. }
+            | Repo::File { severity, .. }
+            | Repo::Pattern { severity, .. } => severity.unwrap_or_default(),
+            Repo::None {} => Severity::default(),
+        }
+    }
+
+    /// Whether `validate --fix` may write the node over the folder.
+    ///
+    /// Optional files and files checked with assertions rather than a hash are considered
+    /// customized by the user and left alone.
+    pub fn is_fixable(&self) -> bool {
+        match self {
+            Repo::Directory { .. } => !self.is_forbidden(),
+            Repo::File {
+                original_md5,
+                is_optional,
+                check,
+                assertions,
+                schema,
+                ..
+            } => {
+                let customized = is_optional.unwrap_or(false)
+                    || check.unwrap_or_default() < Check::Hash
+                    || (original_md5.is_none() && (assertions.is_some() || schema.is_some()));
+                !customized && !self.is_forbidden()
+            }
+            Repo::Pattern { .. } | Repo::None {} => false,
+        }
+    }
+
+    /// Reports every existing path matching a forbidden node
+    fn collect_forbidden<'a>(&'a self, path: &Path, issues: &mut Vec<Issue<'a>>) -> io::Result<()> {
+        let (names, description) = match self {
+            Repo::Directory {
+                name, description, ..
+            }
+            | Repo::File {
+                name, description, ..
+            } => {
+                let exists = fs::symlink_metadata(path.join(name)).is_ok();
+                (
+                    if exists { vec![name.clone()] } else { vec![] },
+                    description,
+                )
+            }
+            Repo::Pattern {
+                name, description, ..
+            } => (Self::matched_entries(path, name)?, description),
+            Repo::None {} => return Ok(()),
+        };
+
+        for name in names {
+            let forbidden_path = path.join(name);
+            let message = match description {
+                Some(description) => format!(
+                    "Forbidden path {} exists: {}",
+                    forbidden_path.display(),
+                    description
+                ),
+                None => format!("Forbidden path {} exists", forbidden_path.display()),
+            };
+            issues.push(Issue::new(
+                self,
+                forbidden_path,
+                IssueKind::Forbidden,
+                message,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Lists the sorted names of the entries of a directory matching a glob pattern
+    fn matched_entries(path: &Path, pattern: &str) -> io::Result<Vec<String>> {
+        let pattern = glob::Pattern::new(pattern).map_err(io::Error::other)?;
+        let mut matched = Vec::new();
+        if let Ok(entries) = fs::read_dir(path) {
+            for entry in entries {
+                let file_name = entry?.file_name().to_string_lossy().to_string();
+                if pattern.matches(&file_name) {
+                    matched.push(file_name);
+                }
+            }
+        }
+        matched.sort();
+        Ok(matched)
+    }
+
+    /// Returns the node at a path relative to the root (e.g. `src/lib.rs`, `.` for the root)
+    pub fn find<P: AsRef<Path>>(&self, path: P) -> io::Result<&Repo> {
+        let path = Self::normalized(path.as_ref());
+        self.nodes()
+            .into_iter()
+            .find(|(node_path, node)| *node_path == path && !matches!(node, Repo::Pattern { .. }))
+            .map(|(_, node)| node)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{} not found in the kraglefile", path.display()),
+                )
+            })
+    }
+
+    /// Lists the file nodes of the structure with their path relative to the root
+    pub fn files(&self) -> Vec<(PathBuf, &Repo)> {
+        self.nodes()
+            .into_iter()
+            .filter(|(_, node)| matches!(node, Repo::File { .. }))
+            .collect()
+    }
+
+    /// Lists the directory, file and pattern nodes of the structure (depth first) with their path
+    /// relative to the root
+    pub fn nodes(&self) -> Vec<(PathBuf, &Repo)> {
+        let mut nodes = Vec::new();
+        self.collect_nodes(Path::new(""), &mut nodes);
+        nodes
+    }
+
+    fn collect_nodes<'a>(&'a self, parent: &Path, nodes: &mut Vec<(PathBuf, &'a Repo)>) {
+        match self {
+            Repo::Directory { name, children, .. } => {
+                let dir_path = Self::normalized(&parent.join(name));
+                nodes.push((dir_path.clone(), self));
+                for child in children {
+                    child.collect_nodes(&dir_path, nodes);
+                }
+            }
+            Repo::File { name, .. } | Repo::Pattern { name, .. } => {
+                nodes.push((Self::normalized(&parent.join(name)), self))
+            }
+            Repo::None {} => {}
+        }
+    }
+
+    /// Removes the `.` components of a path
+    pub fn normalized(path: &Path) -> PathBuf {
+        path.components()
+            .filter(|component| !matches!(component, std::path::Component::CurDir))
+            .collect()
+    }
+
+    /// How the content of a file node is stored, `None` for other nodes
+    pub fn storage(&self) -> Option<Storage> {
+        match self {
+            Repo::File {
+                codec,
+                encoding,
+                is_compressed,
+                ..
+            } => Some(Storage::of_node(*codec, *encoding, *is_compressed)),
+            _ => None,
+        }
+    }
+
+    /// Returns the original bytes of a file node
+    pub fn decoded(&self) -> io::Result<Vec<u8>> {
+        match (self, self.storage()) {
+            (Repo::File { content, .. }, Some(storage)) => storage.decode(content),
+            _ => Err(io::Error::other("Not a file node")),
+        }
+    }
+
+    /// Stores the bytes of a file in the `content` of a file node (see [`Storage::choose`]), its
+    /// size and md5 being left as they are
+    pub fn set_content(&mut self, data: &[u8], storage: Storage) -> io::Result<()> {
+        if let Repo::File {
+            content,
+            codec,
+            encoding,
+            is_compressed,
+            ..
+        } = self
+        {
+            let (storage, encoded) = storage.choose(data)?;
+            *content = encoded;
+            (*codec, *encoding) = storage.fields();
+            *is_compressed = None;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn is_binary(path: &PathBuf) -> bool {
         let binary_exts = [
             "exe", "bin", "png", "jpg", "jpeg", "gif", "bmp", "ico", "pdf", "zip", "rar", "tar",
             "gz", "7z", "dll", "so", "mp3", "mp4", "avi", "mov", "ogg", "wav", "flac", "class",
@@ -417,16 +1056,25 @@ fn test_create_file() {
         let md5_checksum = format!("{:x}", md5::compute(content));
         let repo = Repo::File {
             name: "file.txt".to_string(),
-            is_compressed: Some(false),
+            codec: Some(Codec::None),
+            encoding: None,
+            is_compressed: None,
             content: content.to_string(),
             original_size: Some(0),
             original_md5: Some(md5_checksum),
             is_optional: Some(false),
+            check: None,
+            assertions: None,
+            schema: None,
+            description: None,
+            forbidden: None,
+            severity: None,
         };
 
         fs::create_dir_all(&target_path).unwrap();
 
-        repo.to_folder(&target_path).unwrap();
+        repo.to_folder(&target_path, &mut Dialog::new(String::new()))
+            .unwrap();
 
         assert!(fs::metadata(target_path.join("file.txt")).is_ok());
         let file_content = fs::read_to_string(target_path.join("file.txt")).unwrap();
@@ -439,18 +1087,312 @@ fn test_create_file() {
     fn test_create_directory() {
         let (_, target_path) = setup_temp_dir();
         let repo = Repo::Directory {
+            kragle_version: None,
             name: "dir".to_string(),
             children: vec![],
             dependencies: None,
             description: None,
+            parameters: None,
+            forbidden: None,
+            severity: None,
         };
 
         fs::create_dir_all(&target_path).unwrap();
 
-        repo.to_folder(&target_path).unwrap();
+        repo.to_folder(&target_path, &mut Dialog::new(String::new()))
+            .unwrap();
 
         assert!(fs::metadata(target_path.join("dir")).is_ok());
 
         fs::remove_dir_all(target_path).unwrap();
     }
+
+    #[test]
+    fn test_validated_assertions() {
+        let (_temp_dir, target_path) = setup_temp_dir();
+        fs::write(
+            target_path.join("Cargo.toml"),
+            "[package]\nedition = \"2024\"\n",
+        )
+        .unwrap();
+
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: file
+name: Cargo.toml
+check: exists
+assertions:
+  - contains: 'edition = "2024"'
+  - matches: '^\[package\]$'
+  - not_contains: TODO
+  - max_size: 1024
+  - line_count: {min: 1, max: 10}
+"#,
+        )
+        .unwrap();
+
+        match &repo {
+            Repo::File {
+                check, assertions, ..
+            } => {
+                assert_eq!(*check, Some(Check::Exists));
+                assert_eq!(assertions.as_ref().map(Vec::len), Some(5));
+            }
+            _ => panic!("Expected a file node"),
+        }
+        assert!(repo.validated(&target_path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_validated_undecodable() {
+        let (_temp_dir, target_path) = setup_temp_dir();
+        fs::write(target_path.join("logo.png"), "png").unwrap();
+
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: logo.png
+    content: "not base85 é"
+    codec: xz
+    check: content
+  - type: file
+    name: README.md
+    content: "kragle\n"
+"#,
+        )
+        .unwrap();
+
+        let issues = repo.validated(&target_path).unwrap();
+        let kinds: Vec<IssueKind> = issues.iter().map(|issue| issue.kind).collect();
+        assert_eq!(kinds, vec![IssueKind::Undecodable, IssueKind::Missing]);
+    }
+
+    #[test]
+    fn test_validated_pattern() {
+        let (_temp_dir, target_path) = setup_temp_dir();
+        fs::write(target_path.join("main.rs"), "fn main() {}\n").unwrap();
+        fs::write(target_path.join("lib.rs"), "pub mod repo;\n").unwrap();
+
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: pattern
+name: "*.rs"
+min: 1
+max: 2
+assertions:
+  - max_size: 64
+"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            repo,
+            Repo::Pattern {
+                min: Some(1),
+                max: Some(2),
+                ..
+            }
+        ));
+        assert!(repo.validated(&target_path).unwrap().is_empty());
+
+        let repo = Repo::Pattern {
+            name: "LICENSE*".to_string(),
+            min: None,
+            max: None,
+            description: None,
+            assertions: None,
+            forbidden: None,
+            severity: None,
+        };
+        let issues = repo.validated(&target_path).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].kind, IssueKind::Pattern);
+        assert_eq!(
+            serde_yml::to_string(&repo).unwrap(),
+            "type: pattern\nname: LICENSE*\n"
+        );
+
+        let invalid = Repo::Pattern {
+            name: "[".to_string(),
+            min: None,
+            max: None,
+            description: None,
+            assertions: None,
+            forbidden: None,
+            severity: None,
+        };
+        assert!(invalid.validated(&target_path).is_err());
+    }
+
+    #[test]
+    fn test_forbidden() {
+        let (_temp_dir, target_path) = setup_temp_dir();
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: .env
+    forbidden: true
+    description: Secrets must never be committed
+  - type: pattern
+    name: "*.pem"
+    forbidden: true
+  - type: directory
+    name: node_modules
+    forbidden: true
+"#,
+        )
+        .unwrap();
+
+        // Forbidden nodes are never created
+        repo.to_folder(&target_path, &mut Dialog::new(String::new()))
+            .unwrap();
+        assert!(!target_path.join(".env").exists());
+        assert!(!target_path.join("node_modules").exists());
+
+        fs::write(target_path.join(".env"), "TOKEN=secret\n").unwrap();
+        fs::write(target_path.join("key.pem"), "").unwrap();
+        let issues = repo.validated(&target_path).unwrap();
+        assert_eq!(issues.len(), 2);
+        assert!(
+            issues
+                .iter()
+                .all(|issue| issue.kind == IssueKind::Forbidden)
+        );
+        assert!(
+            issues[0]
+                .message
+                .ends_with(": Secrets must never be committed")
+        );
+
+        match &repo {
+            Repo::Directory { children, .. } => {
+                assert!(children.iter().all(Repo::is_forbidden));
+                assert_eq!(
+                    Repo::matched_entries(&target_path.join("."), "*.pem").unwrap(),
+                    vec!["key.pem".to_string()]
+                );
+            }
+            _ => panic!("Expected a directory node"),
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let (_temp_dir, target_path) = setup_temp_dir();
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: README.md
+    content: "kragle\n"
+  - type: directory
+    name: src
+    children:
+      - type: file
+        name: lib.rs
+        content: "pub mod repo;\n"
+      - type: pattern
+        name: "*.rs"
+"#,
+        )
+        .unwrap();
+
+        assert!(matches!(repo.find("."), Ok(Repo::Directory { .. })));
+        assert_eq!(
+            repo.find("./README.md").unwrap().decoded().unwrap(),
+            b"kragle\n"
+        );
+        assert_eq!(
+            repo.find("src/lib.rs").unwrap().decoded().unwrap(),
+            b"pub mod repo;\n"
+        );
+        assert_eq!(
+            repo.find("src/*.rs").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+
+        // Only the subtree is written
+        repo.find("src")
+            .unwrap()
+            .to_folder(&target_path, &mut Dialog::new(String::new()))
+            .unwrap();
+        assert!(target_path.join("src/lib.rs").exists());
+        assert!(!target_path.join("README.md").exists());
+    }
+
+    #[test]
+    fn test_from_folder_with_config() {
+        let (_temp_dir, target_path) = setup_temp_dir();
+        fs::create_dir_all(target_path.join("target/debug")).unwrap();
+        fs::write(target_path.join("target/debug/kragle"), "\0").unwrap();
+        fs::write(target_path.join("README.md"), "# kragle\n").unwrap();
+        fs::write(target_path.join("notes.txt"), "kragle\n").unwrap();
+        fs::write(
+            target_path.join(".kragle.yaml"),
+            "description: Rust library\nignore: [target]\noptional: [README.md]\n\
+             compressed: [\"*.txt\"]\nparameters:\n  project_name: kragle\n",
+        )
+        .unwrap();
+
+        let repo = Repo::from_folder(&target_path, Storage::default(), 0).unwrap();
+        let Repo::Directory {
+            children,
+            description,
+            dependencies,
+            parameters,
+            ..
+        } = &repo
+        else {
+            panic!("Expected a directory node");
+        };
+        assert_eq!(children.len(), 2);
+        assert_eq!(description.as_deref(), Some("Rust library"));
+        assert!(dependencies.is_none());
+        assert_eq!(parameters.as_ref().unwrap()["project_name"], "kragle");
+        assert!(matches!(
+            repo.find("README.md").unwrap(),
+            Repo::File {
+                is_optional: Some(true),
+                codec: Some(Codec::None),
+                ..
+            }
+        ));
+        assert!(matches!(
+            repo.find("notes.txt").unwrap(),
+            Repo::File {
+                is_optional: Some(false),
+                codec: Some(Codec::Xz),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn test_source_format() {
+        let toml = b"type = \"directory\"\nname = \".\"\n".to_vec();
+        let source = Source::new("-".to_string(), toml.clone(), None);
+        assert_eq!(source.format, Format::Toml);
+        assert!(matches!(Repo::parse(&source), Ok(Repo::Directory { .. })));
+
+        // The extension wins over the Content-Type, which wins over the content
+        let source = Source::new(
+            "https://example.com/repo".to_string(),
+            toml.clone(),
+            Some("application/json"),
+        );
+        assert_eq!(source.format, Format::Json);
+        let source = Source::new(
+            "https://example.com/repo.toml".to_string(),
+            toml,
+            Some("text/plain"),
+        );
+        assert_eq!(source.format, Format::Toml);
+    }
 }
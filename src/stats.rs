@@ -0,0 +1,197 @@
+use std::fmt::Write;
+use std::io;
+
+use serde::Serialize;
+
+use crate::codec::{Codec, Encoding};
+use crate::repo::Repo;
+use crate::tree::human_size;
+
+/// Size of a file of a kraglefile before and after encoding
+#[derive(Serialize, Debug, PartialEq)]
+pub struct FileStats {
+    pub path: String,
+    /// Size of the decoded content
+    pub original_size: u64,
+    /// Size of the `content` field
+    pub encoded_size: u64,
+    pub codec: Codec,
+    /// `encoded_size / original_size`, `None` for empty files
+    pub ratio: Option<f64>,
+}
+
+/// Composition of a kraglefile
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Stats {
+    pub directories: usize,
+    pub files: usize,
+    pub patterns: usize,
+    /// Files stored as plain text
+    pub text_files: usize,
+    /// Files stored compressed or base85/base64-encoded
+    pub encoded_files: usize,
+    pub original_size: u64,
+    pub encoded_size: u64,
+    /// Files sorted from the largest to the smallest original size
+    pub file_stats: Vec<FileStats>,
+}
+
+impl Stats {
+    pub fn new(repo: &Repo) -> io::Result<Self> {
+        let mut stats = Stats {
+            directories: 0,
+            files: 0,
+            patterns: 0,
+            text_files: 0,
+            encoded_files: 0,
+            original_size: 0,
+            encoded_size: 0,
+            file_stats: Vec::new(),
+        };
+
+        for (path, node) in repo.nodes() {
+            match node {
+                Repo::Directory { .. } => stats.directories += 1,
+                Repo::Pattern { .. } => stats.patterns += 1,
+                Repo::File { content, .. } => {
+                    let storage = node.storage().unwrap_or_default();
+                    let original_size = node.decoded()?.len() as u64;
+                    let encoded_size = content.len() as u64;
+
+                    stats.files += 1;
+                    if storage.encoding != Encoding::Utf8 {
+                        stats.encoded_files += 1;
+                    } else {
+                        stats.text_files += 1;
+                    }
+                    stats.original_size += original_size;
+                    stats.encoded_size += encoded_size;
+                    stats.file_stats.push(FileStats {
+                        path: path.to_string_lossy().replace('\\', "/"),
+                        original_size,
+                        encoded_size,
+                        codec: storage.codec,
+                        ratio: ratio(encoded_size, original_size),
+                    });
+                }
+                Repo::None {} => {}
+            }
+        }
+
+        stats.file_stats.sort_by(|a, b| {
+            b.original_size
+                .cmp(&a.original_size)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+        Ok(stats)
+    }
+
+    /// Renders the totals and the `top` largest files
+    pub fn render(&self, top: usize) -> String {
+        let mut output = String::new();
+        let _ = writeln!(output, "Directories: {}", self.directories);
+        let _ = writeln!(
+            output,
+            "Files: {} ({} text, {} encoded)",
+            self.files, self.text_files, self.encoded_files
+        );
+        if self.patterns > 0 {
+            let _ = writeln!(output, "Patterns: {}", self.patterns);
+        }
+        let _ = writeln!(output, "Original size: {}", human_size(self.original_size));
+        let _ = writeln!(
+            output,
+            "Encoded size: {}{}",
+            human_size(self.encoded_size),
+            percent(ratio(self.encoded_size, self.original_size))
+        );
+
+        if top > 0 && !self.file_stats.is_empty() {
+            let _ = writeln!(output, "\nLargest files:");
+            for file in self.file_stats.iter().take(top) {
+                let _ = writeln!(
+                    output,
+                    "{:>10} -> {:>10}{:<9} {}",
+                    human_size(file.original_size),
+                    human_size(file.encoded_size),
+                    percent(file.ratio),
+                    file.path
+                );
+            }
+        }
+        output
+    }
+}
+
+fn ratio(encoded_size: u64, original_size: u64) -> Option<f64> {
+    (original_size > 0).then(|| encoded_size as f64 / original_size as f64)
+}
+
+fn percent(ratio: Option<f64>) -> String {
+    match ratio {
+        Some(ratio) => format!(" ({:.1}%)", ratio * 100.0),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: README.md
+    content: "kragle kragle kragle\n"
+  - type: directory
+    name: static
+    children:
+      - type: file
+        name: logo.png
+        content: "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRFqGBW@GVZ|H=L?|OL00A2V3op44AGY&-0RR9100dcD"
+        is_compressed: true
+      - type: file
+        name: .gitkeep
+        content: ""
+  - type: pattern
+    name: "*.md"
+"#,
+        )
+        .unwrap();
+
+        let stats = Stats::new(&repo).unwrap();
+        assert_eq!(stats.directories, 2);
+        assert_eq!(stats.files, 3);
+        assert_eq!(stats.patterns, 1);
+        assert_eq!(stats.text_files, 2);
+        assert_eq!(stats.encoded_files, 1);
+        assert_eq!(stats.file_stats[1].codec, Codec::Xz);
+        assert_eq!(stats.original_size, 24);
+        assert_eq!(stats.encoded_size, 21 + 75);
+
+        let paths: Vec<&str> = stats
+            .file_stats
+            .iter()
+            .map(|file| file.path.as_str())
+            .collect();
+        assert_eq!(
+            paths,
+            vec!["README.md", "static/logo.png", "static/.gitkeep"]
+        );
+        assert_eq!(stats.file_stats[0].ratio, Some(1.0));
+        assert_eq!(stats.file_stats[1].ratio, Some(25.0));
+        assert_eq!(stats.file_stats[2].ratio, None);
+
+        let rendered = stats.render(1);
+        assert!(
+            rendered.starts_with("Directories: 2\nFiles: 3 (2 text, 1 encoded)\nPatterns: 1\n")
+        );
+        assert!(rendered.contains("Encoded size: 96 B (400.0%)\n"));
+        assert!(rendered.ends_with("      21 B ->       21 B (100.0%) README.md\n"));
+    }
+}
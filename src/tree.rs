@@ -0,0 +1,346 @@
+use std::io;
+use std::path::Path;
+
+use console::Style;
+use serde::Serialize;
+
+use crate::codec::Codec;
+use crate::repo::Repo;
+
+/// Which nodes `kragle tree` shows
+#[derive(Debug, Default)]
+pub struct TreeOptions {
+    /// Deepest level shown, the children of the root being at level 1
+    pub depth: Option<usize>,
+    /// Path (e.g. `src`) or glob (e.g. `**/*.rs`) the shown nodes must match, their ancestors
+    /// being kept for context
+    pub filter: Option<String>,
+}
+
+/// Dependency of a directory along with the location it is loaded from
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Dependency {
+    pub uri: String,
+    pub source: String,
+}
+
+/// Node of the structure as shown by `kragle tree`
+#[derive(Serialize, Debug, PartialEq)]
+pub struct TreeNode {
+    pub name: String,
+    /// Path relative to the root
+    pub path: String,
+    #[serde(rename = "type")]
+    pub node_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub codec: Option<Codec>,
+    pub optional: bool,
+    pub forbidden: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<Dependency>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TreeNode>,
+}
+
+impl TreeNode {
+    /// Builds the tree of a structure, `None` when no node matches the filter
+    pub fn new(repo: &Repo, options: &TreeOptions) -> io::Result<Option<Self>> {
+        let filter = match &options.filter {
+            Some(filter) => Some(glob::Pattern::new(filter).map_err(io::Error::other)?),
+            None => None,
+        };
+        Ok(Self::build(
+            repo,
+            Path::new(""),
+            0,
+            options,
+            filter.as_ref(),
+        ))
+    }
+
+    fn build(
+        repo: &Repo,
+        parent: &Path,
+        level: usize,
+        options: &TreeOptions,
+        filter: Option<&glob::Pattern>,
+    ) -> Option<Self> {
+        let name = repo.name()?;
+        let path = Repo::normalized(&parent.join(name));
+        let mut node = TreeNode {
+            name: name.to_string(),
+            path: match path.to_string_lossy().replace('\\', "/") {
+                root if root.is_empty() => ".".to_string(),
+                path => path,
+            },
+            node_type: "",
+            size: None,
+            codec: None,
+            optional: false,
+            forbidden: repo.is_forbidden(),
+            description: None,
+            dependencies: Vec::new(),
+            children: Vec::new(),
+        };
+
+        match repo {
+            Repo::Directory {
+                children,
+                dependencies,
+                description,
+                ..
+            } => {
+                node.node_type = "directory";
+                node.description = description.clone();
+                node.dependencies = dependencies
+                    .iter()
+                    .flatten()
+                    .map(|uri| Dependency {
+                        uri: uri.clone(),
+                        source: Repo::whereis(uri).unwrap_or_else(|_| uri.clone()),
+                    })
+                    .collect();
+                if options.depth.is_none_or(|depth| level < depth) {
+                    node.children = children
+                        .iter()
+                        .filter_map(|child| Self::build(child, &path, level + 1, options, filter))
+                        .collect();
+                }
+            }
+            Repo::File {
+                original_size,
+                is_optional,
+                description,
+                ..
+            } => {
+                node.node_type = "file";
+                node.size = *original_size;
+                node.codec = repo.storage().map(|storage| storage.codec);
+                node.optional = is_optional.unwrap_or(false);
+                node.description = description.clone();
+            }
+            Repo::Pattern { description, .. } => {
+                node.node_type = "pattern";
+                node.description = description.clone();
+            }
+            Repo::None {} => return None,
+        }
+
+        let shown = match filter {
+            None => true,
+            Some(filter) => {
+                level == 0
+                    || filter.matches_path(&path)
+                    || path.starts_with(filter.as_str())
+                    || !node.children.is_empty()
+            }
+        };
+        shown.then_some(node)
+    }
+
+    /// Renders the tree with box-drawing characters, colors being disabled when stdout is not a
+    /// terminal
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        self.render_node("", true, &mut output);
+        output
+    }
+
+    fn render_node(&self, prefix: &str, last: bool, output: &mut String) {
+        let name = match self.node_type {
+            "directory" => Style::new().blue().apply_to(&self.name).to_string(),
+            "pattern" => Style::new().cyan().apply_to(&self.name).to_string(),
+            _ => self.name.clone(),
+        };
+        let mut line = format!("{}{} {}", prefix, if last { "└──" } else { "├──" }, name);
+
+        let mut details = Vec::new();
+        if let Some(size) = self.size {
+            details.push(human_size(size));
+        }
+        if let Some(codec) = self.codec
+            && codec != Codec::None
+        {
+            details.push(codec.to_string());
+        }
+        if !details.is_empty() {
+            line.push_str(&format!(" ({})", details.join(", ")));
+        }
+        if self.optional {
+            line.push_str(&format!(
+                " {}",
+                Style::new().yellow().apply_to("[optional]")
+            ));
+        }
+        if self.forbidden {
+            line.push_str(&format!(" {}", Style::new().red().apply_to("[forbidden]")));
+        }
+        for dependency in &self.dependencies {
+            let source = if dependency.source == dependency.uri {
+                dependency.uri.clone()
+            } else {
+                format!("{} from {}", dependency.uri, dependency.source)
+            };
+            line.push_str(&format!(
+                " {}",
+                Style::new()
+                    .magenta()
+                    .apply_to(format!("[depends on {}]", source))
+            ));
+        }
+        if let Some(description) = &self.description {
+            line.push_str(&format!(
+                " {}",
+                Style::new().dim().apply_to(format!("# {}", description))
+            ));
+        }
+        output.push_str(&line);
+        output.push('\n');
+
+        let new_prefix = format!("{}{}", prefix, if last { "    " } else { "│   " });
+        let count = self.children.len();
+        for (i, child) in self.children.iter().enumerate() {
+            child.render_node(&new_prefix, i == count - 1, output);
+        }
+    }
+}
+
+/// Formats a number of bytes with a binary unit (e.g. `1.5 KiB`)
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const KRAGLEFILE: &str = r#"
+type: directory
+name: "."
+dependencies:
+  - ./base.yaml
+children:
+  - type: file
+    name: README.md
+    content: "..."
+    original_size: 2048
+    is_compressed: true
+    description: Project readme
+  - type: directory
+    name: src
+    children:
+      - type: file
+        name: lib.rs
+        content: ""
+        original_size: 0
+      - type: directory
+        name: bin
+        children:
+          - type: file
+            name: main.rs
+            content: ""
+            is_optional: true
+  - type: pattern
+    name: "*.log"
+    forbidden: true
+"#;
+
+    fn paths(node: &TreeNode, collected: &mut Vec<PathBuf>) {
+        collected.push(PathBuf::from(&node.path));
+        for child in &node.children {
+            paths(child, collected);
+        }
+    }
+
+    fn shown(options: TreeOptions) -> Vec<PathBuf> {
+        let repo: Repo = serde_yml::from_str(KRAGLEFILE).unwrap();
+        let tree = TreeNode::new(&repo, &options).unwrap().unwrap();
+        let mut shown = Vec::new();
+        paths(&tree, &mut shown);
+        shown
+    }
+
+    #[test]
+    fn test_depth_and_filter() {
+        assert_eq!(shown(TreeOptions::default()).len(), 7);
+        assert_eq!(
+            shown(TreeOptions {
+                depth: Some(1),
+                filter: None
+            }),
+            vec![
+                PathBuf::from("."),
+                PathBuf::from("README.md"),
+                PathBuf::from("src"),
+                PathBuf::from("*.log")
+            ]
+        );
+        assert_eq!(
+            shown(TreeOptions {
+                depth: None,
+                filter: Some("src/bin".to_string())
+            }),
+            vec![
+                PathBuf::from("."),
+                PathBuf::from("src"),
+                PathBuf::from("src/bin"),
+                PathBuf::from("src/bin/main.rs")
+            ]
+        );
+        assert_eq!(
+            shown(TreeOptions {
+                depth: None,
+                filter: Some("**/*.rs".to_string())
+            }),
+            vec![
+                PathBuf::from("."),
+                PathBuf::from("src"),
+                PathBuf::from("src/lib.rs"),
+                PathBuf::from("src/bin"),
+                PathBuf::from("src/bin/main.rs")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        console::set_colors_enabled(false);
+        let repo: Repo = serde_yml::from_str(KRAGLEFILE).unwrap();
+        let tree = TreeNode::new(&repo, &TreeOptions::default())
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            tree.render(),
+            "└── . [depends on ./base.yaml]
+    ├── README.md (2.0 KiB, xz) # Project readme
+    ├── src
+    │   ├── lib.rs (0 B)
+    │   └── bin
+    │       └── main.rs [optional]
+    └── *.log [forbidden]
+"
+        );
+    }
+
+    #[test]
+    fn test_human_size() {
+        assert_eq!(human_size(0), "0 B");
+        assert_eq!(human_size(1536), "1.5 KiB");
+        assert_eq!(human_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
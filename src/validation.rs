@@ -0,0 +1,273 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::dialog::{self, Dialog};
+use crate::repo::Repo;
+
+/// How serious a failed check is
+#[derive(
+    Serialize,
+    Deserialize,
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Default,
+    JsonSchema,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Info,
+    Warning,
+    #[default]
+    Error,
+}
+
+impl Severity {
+    pub fn status(&self) -> dialog::Status {
+        match self {
+            Severity::Info => dialog::Status::Info,
+            Severity::Warning => dialog::Status::Warning,
+            Severity::Error => dialog::Status::Failed,
+        }
+    }
+}
+
+/// Kind of problem found while validating a folder
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    /// The path does not exist
+    Missing,
+    /// The path is a file where a directory is expected, or the opposite
+    WrongType,
+    /// The size, md5 or content of a file differs from the structure
+    Mismatch,
+    /// The content of the file node does not decode, the file cannot be compared nor restored
+    Undecodable,
+    /// A content assertion failed
+    Assertion,
+    /// The content of a file does not follow its JSON Schema
+    Schema,
+    /// A pattern matched too few or too many entries
+    Pattern,
+    /// A forbidden path exists
+    Forbidden,
+}
+
+/// Problem found by `Repo::validated`
+#[derive(Debug)]
+pub struct Issue<'a> {
+    /// Node of the structure that failed
+    pub node: &'a Repo,
+    /// Path on disk the issue is about
+    pub path: PathBuf,
+    pub kind: IssueKind,
+    /// Severity of the node that failed
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl<'a> Issue<'a> {
+    pub fn new(node: &'a Repo, path: PathBuf, kind: IssueKind, message: String) -> Self {
+        Issue {
+            node,
+            path,
+            kind,
+            severity: node.severity(),
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Issue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.severity.status().colorized(), self.message)
+    }
+}
+
+/// Whether some issue is at least as serious as `fail_on`
+pub fn failed(issues: &[Issue], fail_on: Severity) -> bool {
+    issues.iter().any(|issue| issue.severity >= fail_on)
+}
+
+/// Prints how many issues of each severity were found
+pub fn print_summary(issues: &[Issue]) {
+    let count = |severity| {
+        issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    };
+    let (errors, warnings, infos) = (
+        count(Severity::Error),
+        count(Severity::Warning),
+        count(Severity::Info),
+    );
+    let status = if errors > 0 {
+        dialog::Status::Failed
+    } else if warnings > 0 {
+        dialog::Status::Warning
+    } else {
+        dialog::Status::Ok
+    };
+    Dialog::new(format!(
+        "Validation: {} errors, {} warnings, {} infos",
+        errors, warnings, infos
+    ))
+    .end_print(status);
+}
+
+/// Restores missing files and overwrites mismatched ones with the content of the structure.
+///
+/// Overwriting asks for confirmation unless `yes` is set. Nodes that are not fixable
+/// (see `Repo::is_fixable`) are left alone, and the dependencies of a missing directory are not
+/// imported.
+pub fn fix(issues: &[Issue], yes: bool) -> io::Result<()> {
+    let mut fixed: Vec<&Path> = Vec::new();
+
+    for issue in issues {
+        let overwrite = match issue.kind {
+            IssueKind::Missing => false,
+            IssueKind::Mismatch => true,
+            _ => continue,
+        };
+        if fixed.contains(&issue.path.as_path()) {
+            continue;
+        }
+        fixed.push(&issue.path);
+
+        if !issue.node.is_fixable() {
+            let dialog = Dialog::new(format!("Skipped customized {}", issue.path.display()));
+            dialog.end_print(dialog::Status::Warning);
+            continue;
+        }
+        if overwrite && !yes && !dialog::confirm(&format!("Overwrite {}?", issue.path.display()))? {
+            continue;
+        }
+
+        let parent = issue.path.parent().unwrap_or(Path::new("."));
+        let mut dialog = Dialog::new(format!("Restoring {}", issue.path.display()));
+        issue.node.restore(parent, &mut dialog)?;
+        dialog.set_msg(format!("Restored {}", issue.path.display()));
+        dialog.end_print(dialog::Status::Ok);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    const KRAGLEFILE: &str = r##"
+type: directory
+name: "."
+children:
+  - type: file
+    name: README.md
+    content: "# kragle\n"
+    original_size: 9
+    original_md5: f6ed5bb310a2f396778aa429eaca1113
+  - type: file
+    name: Cargo.toml
+    content: "[package]\n"
+    original_md5: 025b13374b8ccc8cf79ba217ac543530
+    is_optional: true
+  - type: file
+    name: LICENSE
+    check: exists
+    assertions:
+      - contains: MIT
+  - type: directory
+    name: src
+    dependencies:
+      - ./does-not-exist.yaml
+    children:
+      - type: file
+        name: lib.rs
+        content: "pub mod repo;\n"
+"##;
+
+    #[test]
+    fn test_fix() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path();
+        fs::write(path.join("README.md"), "# customized\n").unwrap();
+        fs::write(path.join("Cargo.toml"), "[workspace]\n").unwrap();
+
+        let repo: Repo = serde_yml::from_str(KRAGLEFILE).unwrap();
+        let issues = repo.validated(path).unwrap();
+        let kinds: Vec<IssueKind> = issues.iter().map(|issue| issue.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                IssueKind::Mismatch,
+                IssueKind::Mismatch,
+                IssueKind::Mismatch,
+                IssueKind::Missing,
+                IssueKind::Missing
+            ]
+        );
+
+        fix(&issues, true).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(path.join("README.md")).unwrap(),
+            "# kragle\n"
+        );
+        // Optional files are left alone
+        assert_eq!(
+            fs::read_to_string(path.join("Cargo.toml")).unwrap(),
+            "[workspace]\n"
+        );
+        // Nodes using assertions instead of a hash are not restored
+        assert!(!path.join("LICENSE").exists());
+        assert!(failed(&issues, Severity::Error));
+        assert_eq!(
+            fs::read_to_string(path.join("src/lib.rs")).unwrap(),
+            "pub mod repo;\n"
+        );
+    }
+
+    #[test]
+    fn test_severity() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo: Repo = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: .editorconfig
+    check: exists
+    severity: warning
+  - type: pattern
+    name: "CHANGELOG*"
+    severity: info
+"#,
+        )
+        .unwrap();
+
+        let issues = repo.validated(temp_dir.path()).unwrap();
+        let severities: Vec<Severity> = issues.iter().map(|issue| issue.severity).collect();
+        assert_eq!(severities, vec![Severity::Warning, Severity::Info]);
+
+        assert!(!failed(&issues, Severity::Error));
+        assert!(failed(&issues, Severity::Warning));
+        assert!(failed(&issues, Severity::Info));
+        assert!(
+            issues[0]
+                .to_string()
+                .ends_with(".editorconfig not found: No such file or directory (os error 2)")
+        );
+    }
+}
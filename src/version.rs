@@ -0,0 +1,258 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::codec::Storage;
+use crate::repo::Repo;
+
+/// Version of the kraglefile format written by this build.
+///
+/// Kraglefiles without a `kragle_version` field are version 1.
+pub const CURRENT_VERSION: u64 = 3;
+
+/// Field rewritten while migrating a kraglefile to the current version
+#[derive(Debug, PartialEq, Eq)]
+pub struct Deprecation {
+    /// Path of the node in the structure
+    pub path: String,
+    pub field: String,
+    pub message: String,
+}
+
+impl fmt::Display for Deprecation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: field `{}` {}", self.path, self.field, self.message)
+    }
+}
+
+/// One line per deprecated field with the number of nodes using it, for the warnings printed
+/// while loading a kraglefile
+pub fn summarize(deprecations: &[Deprecation]) -> Vec<String> {
+    let mut fields: BTreeMap<&str, BTreeSet<&str>> = BTreeMap::new();
+    for deprecation in deprecations {
+        fields
+            .entry(&deprecation.field)
+            .or_default()
+            .insert(&deprecation.path);
+    }
+    fields
+        .into_iter()
+        .map(|(field, paths)| {
+            let replacement = match field {
+                "is_compressed" => ", use `codec`",
+                _ => "",
+            };
+            format!(
+                "`{}` ({} {}) is deprecated{}",
+                field,
+                paths.len(),
+                if paths.len() == 1 { "node" } else { "nodes" },
+                replacement
+            )
+        })
+        .collect()
+}
+
+/// Format version of a raw kraglefile
+pub fn version(value: &Value) -> io::Result<u64> {
+    match value.get("kragle_version") {
+        None => Ok(1),
+        Some(version) => match version.as_u64() {
+            Some(version) if version >= 1 => Ok(version),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid kragle_version {}", version),
+            )),
+        },
+    }
+}
+
+/// Rewrites a raw kraglefile of any supported version to the current format, returning the
+/// fields that changed
+pub fn migrate(value: &mut Value) -> io::Result<Vec<Deprecation>> {
+    let version = version(value)?;
+    if version > CURRENT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "kragle_version {} is newer than the supported version {}, update kragle",
+                version, CURRENT_VERSION
+            ),
+        ));
+    }
+
+    let mut deprecations = Vec::new();
+    for from in version..CURRENT_VERSION {
+        match from {
+            1 => migrate_v1(value, Path::new(""), &mut deprecations),
+            2 => migrate_v2(value, Path::new(""), &mut deprecations),
+            _ => unreachable!("no migration from version {}", from),
+        }
+    }
+
+    if let Some(root) = value.as_object_mut()
+        && root.get("type").and_then(Value::as_str) == Some("directory")
+    {
+        root.insert("kragle_version".to_string(), Value::from(CURRENT_VERSION));
+    }
+    Ok(deprecations)
+}
+
+/// Version 1 exports stored binary files compressed while writing `is_compressed: false`
+fn migrate_v1(node: &mut Value, parent: &Path, deprecations: &mut Vec<Deprecation>) {
+    let Some(fields) = node.as_object_mut() else {
+        return;
+    };
+    let name = fields.get("name").and_then(Value::as_str).unwrap_or("");
+    let path = Repo::normalized(&parent.join(name));
+
+    // Nodes with a `codec` were written by a version 3 kragle (e.g. `kragle add`)
+    if fields.get("type").and_then(Value::as_str) == Some("file")
+        && fields.get("codec").is_none_or(Value::is_null)
+        && !fields
+            .get("is_compressed")
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+        && let (Some(content), Some(original_md5)) = (
+            fields.get("content").and_then(Value::as_str),
+            fields.get("original_md5").and_then(Value::as_str),
+        )
+        && format!("{:x}", md5::compute(content)) != original_md5
+        && let Ok(decoded) = Storage::of_node(None, None, Some(true)).decode(content)
+        && format!("{:x}", md5::compute(&decoded)) == original_md5
+    {
+        fields.insert("is_compressed".to_string(), Value::Bool(true));
+        deprecations.push(Deprecation {
+            path: path.to_string_lossy().to_string(),
+            field: "is_compressed".to_string(),
+            message: "set to true: version 1 exports compressed binary files without flagging them"
+                .to_string(),
+        });
+    }
+
+    if let Some(Value::Array(children)) = fields.get_mut("children") {
+        for child in children {
+            migrate_v1(child, &path, deprecations);
+        }
+    }
+}
+
+/// Version 3 replaces `is_compressed` with `codec`, the content being unchanged (base85 is the
+/// default encoding of compressed contents)
+fn migrate_v2(node: &mut Value, parent: &Path, deprecations: &mut Vec<Deprecation>) {
+    let Some(fields) = node.as_object_mut() else {
+        return;
+    };
+    let name = fields.get("name").and_then(Value::as_str).unwrap_or("");
+    let path = Repo::normalized(&parent.join(name));
+
+    if fields.get("type").and_then(Value::as_str) == Some("file")
+        && let Some(is_compressed) = fields.remove("is_compressed")
+    {
+        let codec = if is_compressed.as_bool().unwrap_or(false) {
+            "xz"
+        } else {
+            "none"
+        };
+        if fields.get("codec").is_none_or(Value::is_null) {
+            fields.insert("codec".to_string(), Value::from(codec));
+        }
+        deprecations.push(Deprecation {
+            path: path.to_string_lossy().to_string(),
+            field: "is_compressed".to_string(),
+            message: format!("replaced by `codec: {}` in version 3", codec),
+        });
+    }
+
+    if let Some(Value::Array(children)) = fields.get_mut("children") {
+        for child in children {
+            migrate_v2(child, &path, deprecations);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate() {
+        let mut value: Value = serde_yml::from_str(
+            r#"
+type: directory
+name: "."
+children:
+  - type: directory
+    name: static
+    children:
+      - type: file
+        name: logo.png
+        content: "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRFqGBW@GVZ|H=L?|OL00A2V3op44AGY&-0RR9100dcD"
+        original_md5: 202cb962ac59075b964b07152d234b70
+        is_compressed: false
+      - type: file
+        name: robots.txt
+        content: "123"
+        original_md5: 202cb962ac59075b964b07152d234b70
+        is_compressed: false
+      # Written by `kragle add` into a version 1 kraglefile
+      - type: file
+        name: favicon.ico
+        content: "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRFqGBW@GVZ|H=L?|OL00A2V3op44AGY&-0RR9100dcD"
+        original_md5: 202cb962ac59075b964b07152d234b70
+        codec: xz
+"#,
+        )
+        .unwrap();
+        let content = value["children"][0]["children"][0]["content"].clone();
+        let content = Storage::of_node(None, None, Some(true))
+            .decode(content.as_str().unwrap())
+            .unwrap();
+        assert_eq!(content, b"123");
+
+        let deprecations = migrate(&mut value).unwrap();
+        assert_eq!(
+            deprecations
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            vec![
+                "static/logo.png: field `is_compressed` set to true: version 1 exports compressed \
+                 binary files without flagging them",
+                "static/logo.png: field `is_compressed` replaced by `codec: xz` in version 3",
+                "static/robots.txt: field `is_compressed` replaced by `codec: none` in version 3",
+            ]
+        );
+        assert_eq!(
+            summarize(&deprecations),
+            vec!["`is_compressed` (2 nodes) is deprecated, use `codec`"]
+        );
+        assert_eq!(value["kragle_version"], CURRENT_VERSION);
+        assert_eq!(value["children"][0]["children"][0]["codec"], "xz");
+        assert_eq!(value["children"][0]["children"][1]["codec"], "none");
+        assert!(
+            value["children"][0]["children"][0]
+                .get("is_compressed")
+                .is_none()
+        );
+        assert_eq!(
+            value["children"][0]["children"][2],
+            serde_json::json!({
+                "type": "file",
+                "name": "favicon.ico",
+                "content": "{Wp48S^xk9=GL@E0stWa761SMbT8$j0RRFqGBW@GVZ|H=L?|OL00A2V3op44AGY&-0RR9100dcD",
+                "original_md5": "202cb962ac59075b964b07152d234b70",
+                "codec": "xz",
+            })
+        );
+
+        // Already migrated
+        assert!(migrate(&mut value).unwrap().is_empty());
+
+        value["kragle_version"] = Value::from(CURRENT_VERSION + 1);
+        assert!(migrate(&mut value).is_err());
+    }
+}
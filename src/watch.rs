@@ -0,0 +1,247 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use console::Term;
+use notify::{RecursiveMode, Watcher};
+
+use crate::dialog::{self, Dialog};
+use crate::repo::Repo;
+
+/// Delay used to group the file system events of a single change
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+/// Node of the structure together with its location on disk
+struct Entry<'a> {
+    node: &'a Repo,
+    /// Directory containing the node
+    parent: PathBuf,
+    /// Path of the node itself
+    path: PathBuf,
+    /// Index following the last descendant of the node
+    end: usize,
+    /// Failing checks reported for the node (descendants excluded)
+    issues: Vec<String>,
+}
+
+/// Validation state of a folder, updated node by node when files change
+pub struct Watch<'a> {
+    root: PathBuf,
+    entries: Vec<Entry<'a>>,
+}
+
+impl<'a> Watch<'a> {
+    pub fn new(repo: &'a Repo, root: &Path) -> io::Result<Self> {
+        let mut watch = Watch {
+            root: fs::canonicalize(root)?,
+            entries: Vec::new(),
+        };
+        let parent = watch.root.clone();
+        watch.flatten(repo, parent);
+        watch.revalidate(&[0])?;
+        Ok(watch)
+    }
+
+    fn flatten(&mut self, node: &'a Repo, parent: PathBuf) {
+        let path = match node {
+            Repo::Directory { name, .. } | Repo::File { name, .. } | Repo::Pattern { name, .. } => {
+                parent.join(name)
+            }
+            Repo::None {} => parent.clone(),
+        };
+        let index = self.entries.len();
+        self.entries.push(Entry {
+            node,
+            parent,
+            path: path.clone(),
+            end: index + 1,
+            issues: Vec::new(),
+        });
+
+        if let Repo::Directory { children, .. } = node
+            && !node.is_forbidden()
+        {
+            for child in children {
+                self.flatten(child, path.clone());
+            }
+        }
+        self.entries[index].end = self.entries.len();
+    }
+
+    /// Returns the nodes to re-validate after a change of `path`.
+    ///
+    /// Files, patterns and forbidden nodes of the deepest directory containing the path are
+    /// re-validated, subdirectories only when the path is the subdirectory itself.
+    pub fn affected(&self, path: &Path) -> Vec<usize> {
+        let directory = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| {
+                matches!(entry.node, Repo::Directory { .. })
+                    && !entry.node.is_forbidden()
+                    && path.starts_with(&entry.path)
+                    && path != entry.path
+            })
+            .max_by_key(|(_, entry)| entry.path.components().count());
+
+        let Some((index, directory)) = directory else {
+            // The root itself changed
+            return vec![0];
+        };
+
+        let mut affected = Vec::new();
+        let mut child = index + 1;
+        while child < directory.end {
+            let entry = &self.entries[child];
+            let is_directory = matches!(entry.node, Repo::Directory { .. });
+            if !is_directory || entry.node.is_forbidden() || entry.path == path {
+                affected.push(child);
+            }
+            child = entry.end;
+        }
+        affected
+    }
+
+    /// Validates again the given nodes and their descendants
+    pub fn revalidate(&mut self, indexes: &[usize]) -> io::Result<()> {
+        for &index in indexes {
+            let end = self.entries[index].end;
+            for entry in &mut self.entries[index..end] {
+                entry.issues.clear();
+            }
+
+            let node = self.entries[index].node;
+            let issues = node.validated(&self.entries[index].parent)?;
+            for issue in issues {
+                if let Some(owner) =
+                    (index..end).find(|&i| std::ptr::eq(self.entries[i].node, issue.node))
+                {
+                    self.entries[owner].issues.push(issue.to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Failing checks, in the order of the structure
+    pub fn failing(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().flat_map(|entry| entry.issues.iter())
+    }
+
+    /// Prints the current status of the checks
+    fn print_status(&self) -> io::Result<()> {
+        let term = Term::stdout();
+        if term.is_term() {
+            term.clear_screen()?;
+        }
+
+        let mut stdout = io::stdout();
+        for issue in self.failing() {
+            writeln!(stdout, "{}", issue)?;
+        }
+
+        let count = self.failing().count();
+        if count == 0 {
+            let dialog = Dialog::new(format!("Watching {}: all checks pass", self.root.display()));
+            dialog.end_print(dialog::Status::Ok);
+        } else {
+            let dialog = Dialog::new(format!(
+                "Watching {}: {} failing checks",
+                self.root.display(),
+                count
+            ));
+            dialog.end_print(dialog::Status::Failed);
+        }
+        Ok(())
+    }
+
+    /// Watches the folder and re-validates the affected nodes on every change, until interrupted
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        watcher.watch(&self.root, RecursiveMode::Recursive)?;
+
+        self.print_status()?;
+        while let Ok(event) = rx.recv() {
+            let mut paths = BTreeSet::new();
+            paths.extend(event?.paths);
+            while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+                paths.extend(event?.paths);
+            }
+
+            let mut affected = BTreeSet::new();
+            for path in &paths {
+                affected.extend(self.affected(path));
+            }
+            let affected: Vec<usize> = affected.into_iter().collect();
+            self.revalidate(&affected)?;
+            self.print_status()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const KRAGLEFILE: &str = r#"
+type: directory
+name: "."
+children:
+  - type: file
+    name: README.md
+    check: exists
+  - type: pattern
+    name: "*.toml"
+  - type: directory
+    name: src
+    children:
+      - type: file
+        name: lib.rs
+        check: exists
+"#;
+
+    #[test]
+    fn test_affected() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo: Repo = serde_yml::from_str(KRAGLEFILE).unwrap();
+        let watch = Watch::new(&repo, temp_dir.path()).unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+
+        // Entries: 0 root, 1 README.md, 2 *.toml, 3 src, 4 lib.rs
+        assert_eq!(watch.affected(&root.join("Cargo.toml")), vec![1, 2]);
+        assert_eq!(watch.affected(&root.join("src")), vec![1, 2, 3]);
+        assert_eq!(watch.affected(&root.join("src/lib.rs")), vec![4]);
+        assert_eq!(watch.affected(&root), vec![0]);
+    }
+
+    #[test]
+    fn test_revalidate() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo: Repo = serde_yml::from_str(KRAGLEFILE).unwrap();
+        let mut watch = Watch::new(&repo, temp_dir.path()).unwrap();
+        let root = fs::canonicalize(temp_dir.path()).unwrap();
+        // README.md, *.toml and src are missing
+        assert_eq!(watch.failing().count(), 3);
+
+        fs::write(root.join("README.md"), "# kragle\n").unwrap();
+        fs::create_dir(root.join("src")).unwrap();
+        watch
+            .revalidate(&watch.affected(&root.join("src")))
+            .unwrap();
+        // *.toml and src/lib.rs are missing
+        assert_eq!(watch.failing().count(), 2);
+
+        fs::write(root.join("src/lib.rs"), "").unwrap();
+        watch
+            .revalidate(&watch.affected(&root.join("src/lib.rs")))
+            .unwrap();
+        assert_eq!(watch.failing().count(), 1);
+    }
+}